@@ -1,12 +1,68 @@
-use criterion::{criterion_group, criterion_main, Criterion};
-use minefair_field::Field;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use minefair_field::{Field, Judge};
+use savefile::prelude::{load_from_mem, save};
+use std::io::Cursor;
+use std::num::NonZeroUsize;
+
+fn bench_save() -> Field {
+    Field::load(&mut Cursor::new(include_bytes!("bench_save.minefair"))).unwrap()
+}
+
+/// A long game on an infinite board, played by always clicking the safest tile nearest to the middle.
+fn large_save() -> Field {
+    let mut field = Field::new(0.1, Judge::Kind, false, None);
+    field.reveal_cell_first_zero((0, 0));
+    for _ in 0..1500 {
+        let Some((point, _)) = field.risks().nearest((0, 0), field.risks().global_best()) else { break };
+        field.reveal_cell(point);
+    }
+    field
+}
 
 pub fn run_benches(c: &mut Criterion) {
     c.bench_function("slow click test", |b| b.iter(|| {
-        let mut field: Field = bincode::decode_from_slice(include_bytes!("bench_save.minefair"), bincode::config::standard()).unwrap().0;
+        let mut field = bench_save();
         let _ = field.reveal_cell((-3, -8));
         field
     }));
+
+    let mut group = c.benchmark_group("solver threads");
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &threads| b.iter(|| {
+            let mut field = bench_save();
+            field.set_threads(NonZeroUsize::new(threads));
+            let _ = field.reveal_cell((-3, -8));
+            field
+        }));
+    }
+    group.finish();
+
+    // compare the current format to writing `Field` as it is, like saves did before version 3
+    let field = large_save();
+    let mut compact = Vec::new();
+    field.save(&mut compact).unwrap();
+    let mut full = Vec::new();
+    save(&mut full, 2, &field).unwrap();
+    println!(
+        "large save with {} tiles revealed: {} bytes compact, {} bytes uncompressed",
+        field.cells_revealed(), compact.len(), full.len(),
+    );
+
+    let mut group = c.benchmark_group("save format");
+    group.bench_function("save compact", |b| b.iter(|| {
+        let mut out = Vec::new();
+        field.save(&mut out).unwrap();
+        out
+    }));
+    group.bench_function("save uncompressed", |b| b.iter(|| {
+        let mut out = Vec::new();
+        save(&mut out, 2, &field).unwrap();
+        out
+    }));
+    group.bench_function("load compact", |b| b.iter(|| Field::load(&mut &compact[..]).unwrap()));
+    // this doesn't rebuild the indices that `Field::load` does, so it has a head start
+    group.bench_function("load uncompressed", |b| b.iter(|| load_from_mem::<Field>(&full, 2).unwrap()));
+    group.finish();
 }
 
 criterion_group!(benches, run_benches);
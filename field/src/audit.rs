@@ -0,0 +1,120 @@
+//! A log of the judge's decisions, so a player who disagrees with one can see what it was based on.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::field::*;
+use crate::judges::{Judge, Tolerance};
+
+/// How many decisions the log keeps. Older ones are forgotten, so that a long game doesn't keep using more memory.
+const AUDIT_CAPACITY: usize = 1000;
+
+/// Which cells the best risk was taken from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(rename_all = "kebab-case"))]
+pub enum Scope {
+    /// Every cell on the board.
+    Global,
+    /// The logical region of the clicked cell.
+    Region,
+}
+
+/// The judge's decision about a click.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Decision {
+    pub point: Coord,
+    pub judge: Judge,
+    /// The tolerance the judge allowed. This is always exact for judges other than the threshold judges.
+    pub tolerance: Tolerance,
+    /// The chance of the cell being a mine when it was clicked.
+    pub risk: f32,
+    /// The best risk the cell was compared against, if the judge compared it against one.
+    pub best: Option<(Scope, f32)>,
+    /// Whether the cell was safe.
+    pub clear: bool,
+}
+
+/// Written on one line, like `3 -2: local judge, risk 50%, best in region 25%, mine`.
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (x, y) = self.point;
+        write!(f, "{x} {y}: {} judge", self.judge)?;
+        if self.judge.has_tolerance() {
+            write!(f, " with tolerance {}", self.tolerance)?;
+        }
+        write!(f, ", risk {}%", self.risk * 100.0)?;
+        match self.best {
+            Some((Scope::Global, best)) => write!(f, ", best on board {}%", best * 100.0)?,
+            Some((Scope::Region, best)) => write!(f, ", best in region {}%", best * 100.0)?,
+            None => (),
+        }
+        write!(f, ", {}", if self.clear { "safe" } else { "mine" })
+    }
+}
+
+impl Field {
+    /// Start or stop logging the judge's decisions. The log isn't saved, and stopping clears it.
+    pub fn set_auditing(&mut self, auditing: bool) {
+        if auditing != self.audit_log.is_some() {
+            self.audit_log = auditing.then(VecDeque::new);
+        }
+    }
+
+    /// The decisions the judge has made about the last `AUDIT_CAPACITY` clicks since logging started, oldest first.
+    pub fn decisions(&self) -> impl DoubleEndedIterator<Item=&Decision> + ExactSizeIterator {
+        self.audit_log.as_ref().map_or_else(Default::default, VecDeque::iter)
+    }
+
+    /// Log the judge's decision about a cell the player clicked, if logging is on.
+    /// The decision depends on what's known about the board, so this should be called before the cell is revealed.
+    pub fn audit(&mut self, point: Coord) {
+        if self.audit_log.is_none() {
+            return;
+        }
+        let decision = self.decide(point);
+        let log = self.audit_log.as_mut().unwrap();
+        if log.len() == AUDIT_CAPACITY {
+            log.pop_front();
+        }
+        log.push_back(decision);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audit_log() {
+        let mut field = Field::import_text(&mut "density 0.2\njudge global\nbounds 5x1\norigin -3 -1\n\n#1###\n".as_bytes()).unwrap();
+        field.set_auditing(true);
+        // the cells next to the 1 are riskier than the ones nothing is known about
+        field.audit((-3, -1));
+        assert_eq!(field.reveal_cell((-3, -1)), None);
+        assert_eq!(field.decisions().collect::<Vec<_>>(), [&Decision {
+            point: (-3, -1),
+            judge: Judge::Global,
+            tolerance: Tolerance::default(),
+            risk: 0.5,
+            best: Some((Scope::Global, 0.2)),
+            clear: false,
+        }]);
+        assert_eq!(field.decisions().next().unwrap().to_string(), "-3 -1: global judge, risk 50%, best on board 20%, mine");
+
+        field.clear();
+        assert_eq!(field.decisions().len(), 0);
+        field.set_auditing(false);
+        field.audit((0, -1));
+        assert_eq!(field.decisions().len(), 0);
+
+        // the oldest decisions are the ones forgotten
+        field.set_auditing(true);
+        field.audit((-3, -1));
+        for _ in 0..AUDIT_CAPACITY {
+            field.audit((0, -1));
+        }
+        assert_eq!(field.decisions().len(), AUDIT_CAPACITY);
+        assert!(field.decisions().all(|d| d.point == (0, -1)));
+    }
+}
@@ -0,0 +1,99 @@
+use savefile::prelude::Savefile;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which numbers revealed cells prefer to show, on top of how likely each number is.
+///
+/// Each bias has a strength: 0 makes no difference, 1 makes a number twice as likely for each step better it is, and so on.
+/// Unlike `Field::solvable`, biases don't stop applying when there are safe cells left.
+#[derive(Clone, Copy, Debug, PartialEq, Default, Savefile)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Bias {
+    /// Numbers are chosen only by how likely they are.
+    #[default]
+    None,
+    /// Prefer numbers that leave more cells known to be safe.
+    Generous(f32),
+    /// Prefer numbers that leave fewer cells undecided, being neither known to be safe nor known to be mines.
+    Decisive(f32),
+}
+
+impl Bias {
+    pub(crate) fn is_valid(self) -> bool {
+        match self {
+            Self::None => true,
+            Self::Generous(strength) | Self::Decisive(strength) => strength.is_finite() && strength >= 0.0,
+        }
+    }
+
+    /// Make each number more or less likely, given how many of the cells around the revealed cell would be safe and how many would be
+    /// undecided if it was shown.
+    pub(crate) fn apply(self, weights: &mut [f64], safe: &[usize], undecided: &[usize]) {
+        let score = |num: usize| match self {
+            Self::None => 0.0,
+            Self::Generous(strength) => strength as f64 * safe[num] as f64,
+            Self::Decisive(strength) => -strength as f64 * undecided[num] as f64,
+        };
+        // measured from the best score, so a strong bias can't overflow
+        let best = (0..weights.len()).filter(|&num| weights[num] > 0.0).map(score).fold(f64::NEG_INFINITY, f64::max);
+        for (num, weight) in weights.iter_mut().enumerate() {
+            *weight *= 2f64.powf(score(num) - best);
+        }
+    }
+}
+
+/// Written as the name of the bias followed by its strength, like `generous=1.5`.
+impl fmt::Display for Bias {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Generous(strength) => write!(f, "generous={strength}"),
+            Self::Decisive(strength) => write!(f, "decisive={strength}"),
+        }
+    }
+}
+
+/// The strength can be left out, in which case it's 1.
+impl FromStr for Bias {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, strength) = match s.split_once('=') {
+            Some((name, strength)) => (name, strength.parse().ok().filter(|&s: &f32| s.is_finite() && s >= 0.0).ok_or("invalid strength")?),
+            None => (s, 1.0),
+        };
+        match name {
+            "none" => Ok(Self::None),
+            "generous" => Ok(Self::Generous(strength)),
+            "decisive" => Ok(Self::Decisive(strength)),
+            _ => Err("unknown bias, expected none, generous or decisive"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Field;
+
+    #[test]
+    fn parsing() {
+        for bias in [Bias::None, Bias::Generous(1.5), Bias::Decisive(0.0)] {
+            assert_eq!(bias.to_string().parse(), Ok(bias));
+        }
+        assert_eq!("generous".parse(), Ok(Bias::Generous(1.0)));
+        assert!("decisive=-1".parse::<Bias>().is_err());
+        assert!("decisive=inf".parse::<Bias>().is_err());
+        assert!("kind".parse::<Bias>().is_err());
+    }
+
+    #[test]
+    fn generous() {
+        for _ in 0..20 {
+            // a 0 gives away the last cell, while a 1 would leave it a guess
+            let mut field = Field::import_text(&mut "density 0.5\nbias generous=100\nbounds 3x1\n\n0##\n".as_bytes()).unwrap();
+            assert_eq!(field.reveal_cell((-1, -1)), Some(0));
+            assert_eq!(field.bias(), Bias::Generous(100.0));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Automated players. A `Strategy` looks at a field and decides what to do next; it's up to the caller to do it.
+
+use rand::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::field::*;
+
+/// Something a player can do to the board.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Reveal(Coord),
+    Flag(Coord),
+}
+
+pub trait Strategy {
+    /// Choose what to do next, or `None` if there's nothing the strategy is willing to do.
+    fn next_action(&mut self, field: &Field) -> Option<Action>;
+}
+
+/// Reveals a random cell the judge would accept. It never guesses, so it gets stuck instead of dying.
+pub struct RandomSafe;
+
+impl Strategy for RandomSafe {
+    fn next_action(&mut self, field: &Field) -> Option<Action> {
+        field.safe_frontier().choose(&mut rand::rng()).copied().map(Action::Reveal)
+    }
+}
+
+/// Reveals one of the cells least likely to be a mine, at random.
+pub struct LowestRisk;
+
+impl Strategy for LowestRisk {
+    fn next_action(&mut self, field: &Field) -> Option<Action> {
+        let risks = field.risks();
+        let best = risks.global_best();
+        // cells nobody knows anything about can be safer than anything on the frontier
+        let unknown = risks.keys()
+            .flat_map(|p| field.adjacents(p))
+            .find(|&p| field.get(p) == Some(Cell::Hidden(false)) && !risks.contains_key(p));
+        if let Some(unknown) = unknown && field.cell_risk(unknown) < best {
+            return Some(Action::Reveal(unknown));
+        }
+        risks.iter().filter(|&(_, r)| r == best && r < 1.0).map(|(p, _)| p).choose(&mut rand::rng()).map(Action::Reveal)
+    }
+}
+
+/// Reveals the cell closest to the origin that's known to be safe, or guesses the closest one that isn't known to be a mine.
+pub struct Closest;
+
+impl Strategy for Closest {
+    fn next_action(&mut self, field: &Field) -> Option<Action> {
+        field.risks().iter()
+            .filter(|&(_, r)| r < 1.0)
+            .min_by_key(|&((x, y), r)| (r > 0.0, x*x + y*y, (x, y)))
+            .map(|(p, _)| Action::Reveal(p))
+    }
+}
+
+/// The strategies that come with minefair, for choosing one by name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Bot {
+    Random,
+    LowestRisk,
+    Closest,
+}
+
+impl Bot {
+    pub const ALL: [Bot; 3] = [Bot::Random, Bot::LowestRisk, Bot::Closest];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bot::Random => "random",
+            Bot::LowestRisk => "lowest-risk",
+            Bot::Closest => "closest",
+        }
+    }
+
+    pub fn strategy(&self) -> Box<dyn Strategy + Send> {
+        match self {
+            Bot::Random => Box::new(RandomSafe),
+            Bot::LowestRisk => Box::new(LowestRisk),
+            Bot::Closest => Box::new(Closest),
+        }
+    }
+}
+
+impl fmt::Display for Bot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Bot {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|b| b.name() == s).ok_or("unknown bot")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Judge;
+
+    #[test]
+    fn bots() {
+        for bot in Bot::ALL {
+            assert_eq!(bot.name().parse(), Ok(bot));
+            for _ in 0..10 {
+                let mut field = Field::new(0.15, Judge::Local, false, Some((10, 10)));
+                let mut strategy = bot.strategy();
+                let mut alive = field.reveal_cell_first_zero((0, 0)).is_some();
+                while alive && !field.is_won() {
+                    match strategy.next_action(&field) {
+                        Some(Action::Reveal(point)) => {
+                            assert_eq!(field.get(point), Some(Cell::Hidden(false)));
+                            alive = field.reveal_cell(point).is_some();
+                        },
+                        Some(Action::Flag(point)) => field.toggle_flag(point),
+                        None => break,
+                    }
+                }
+                // the local judge never punishes a click on the safest tile around
+                if bot != Bot::Closest {
+                    assert!(alive, "{bot} died");
+                }
+            }
+        }
+    }
+}
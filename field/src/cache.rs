@@ -3,6 +3,25 @@ use std::cmp::Ordering;
 use std::collections::{HashMap, BTreeSet};
 use crate::field::Coord;
 
+/// Width and height of the squares that the spatial index divides the plane into.
+const BUCKET_SIZE: isize = 16;
+
+fn bucket_of((x, y): Coord) -> Coord {
+    (x.div_euclid(BUCKET_SIZE), y.div_euclid(BUCKET_SIZE))
+}
+
+/// Widen a bounding box of buckets so that it includes the given bucket.
+fn grow_bounds(bounds: &mut Option<(Coord, Coord)>, (bx, by): Coord) {
+    *bounds = Some(match *bounds {
+        Some(((lx, ly), (hx, hy))) => ((lx.min(bx), ly.min(by)), (hx.max(bx), hy.max(by))),
+        None => ((bx, by), (bx, by)),
+    });
+}
+
+fn distance_squared((x1, y1): Coord, (x2, y2): Coord) -> isize {
+    (x1 - x2).pow(2) + (y1 - y2).pow(2)
+}
+
 #[derive(Savefile, Clone, Copy, PartialEq)]
 #[repr(C)]
 struct ByRisk(Coord, f32);
@@ -26,6 +45,14 @@ impl Ord for ByRisk {
 pub struct RiskCache {
     contents: HashMap<Coord, f32>,
     by_risk: BTreeSet<ByRisk>,
+    /// The same as `by_risk`, but split up by location.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    buckets: HashMap<Coord, BTreeSet<ByRisk>>,
+    /// The lowest and highest bucket that has been used on each axis. This isn't shrunk when points are removed.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    bounds: Option<(Coord, Coord)>,
 }
 
 impl RiskCache {
@@ -42,22 +69,43 @@ impl RiskCache {
     }
 
     pub(crate) fn insert(&mut self, point: Coord, risk: f32) {
+        grow_bounds(&mut self.bounds, bucket_of(point));
+        let bucket = self.buckets.entry(bucket_of(point)).or_default();
         if let Some(old_risk) = self.contents.insert(point, risk) {
             if risk == old_risk { return }
             assert!(self.by_risk.remove(&ByRisk(point, old_risk)));
+            assert!(bucket.remove(&ByRisk(point, old_risk)));
         }
         self.by_risk.insert(ByRisk(point, risk));
+        bucket.insert(ByRisk(point, risk));
     }
 
     pub(crate) fn remove(&mut self, point: Coord) {
         if let Some(old_risk) = self.contents.remove(&point) {
             assert!(self.by_risk.remove(&ByRisk(point, old_risk)));
+            let bucket = self.buckets.get_mut(&bucket_of(point)).unwrap();
+            assert!(bucket.remove(&ByRisk(point, old_risk)));
+            if bucket.is_empty() {
+                self.buckets.remove(&bucket_of(point));
+            }
         }
     }
 
     pub(crate) fn clear(&mut self) {
         self.contents.clear();
         self.by_risk.clear();
+        self.buckets.clear();
+        self.bounds = None;
+    }
+
+    /// Rebuild the spatial index, which isn't saved.
+    pub(crate) fn reindex(&mut self) {
+        self.buckets.clear();
+        self.bounds = None;
+        for &entry in &self.by_risk {
+            grow_bounds(&mut self.bounds, bucket_of(entry.0));
+            self.buckets.entry(bucket_of(entry.0)).or_default().insert(entry);
+        }
     }
 
     pub fn global_best(&self) -> f32 {
@@ -83,10 +131,152 @@ impl RiskCache {
     pub fn values(&self) -> impl Iterator<Item=f32> {
         self.contents.values().copied()
     }
+
+    /// The lowest risk of any of the given points, or 1.0 if none of them are in the cache.
+    pub fn best_of(&self, points: impl IntoIterator<Item=Coord>) -> f32 {
+        points.into_iter().filter_map(|p| self.get(p)).fold(1.0, f32::min)
+    }
+
+    /// Iterate over the risks inside the rectangle spanning from `(lx, ly)` (inclusive) to `(hx, hy)` (exclusive).
+    pub fn in_rect(&self, (lx, ly): Coord, (hx, hy): Coord) -> impl Iterator<Item=(Coord, f32)> {
+        let (blx, bly) = bucket_of((lx, ly));
+        let (bhx, bhy) = bucket_of((hx - 1, hy - 1));
+        (bly..=bhy)
+            .flat_map(move |by| (blx..=bhx).map(move |bx| (bx, by)))
+            .filter_map(|b| self.buckets.get(&b))
+            .flatten()
+            .filter(move |&&ByRisk((x, y), _)| lx <= x && x < hx && ly <= y && y < hy)
+            .map(|&ByRisk(p, r)| (p, r))
+    }
+
+    /// The point with the lowest risk inside a rectangle, as in `in_rect`.
+    pub fn best_in_rect(&self, (lx, ly): Coord, (hx, hy): Coord) -> Option<(Coord, f32)> {
+        if lx >= hx || ly >= hy {
+            return None;
+        }
+        let (blx, bly) = bucket_of((lx, ly));
+        let (bhx, bhy) = bucket_of((hx - 1, hy - 1));
+        let mut best = None;
+        for by in bly..=bhy {
+            for bx in blx..=bhx {
+                let Some(bucket) = self.buckets.get(&(bx, by)) else { continue };
+                // buckets are ordered by risk, so the first entry that's inside the rectangle is the best one
+                let found = bucket.iter().find(|&&ByRisk((x, y), _)| lx <= x && x < hx && ly <= y && y < hy);
+                if let Some(&found) = found && best.is_none_or(|b| found < b) {
+                    best = Some(found);
+                }
+            }
+        }
+        best.map(|ByRisk(p, r)| (p, r))
+    }
+
+    /// The closest point to `point` with a risk of at most `max_risk`, by Euclidean distance.
+    pub fn nearest(&self, point: Coord, max_risk: f32) -> Option<(Coord, f32)> {
+        if self.by_risk.first().is_none_or(|e| e.1 > max_risk) {
+            return None;
+        }
+        let ((lx, ly), (hx, hy)) = self.bounds?;
+        let (cx, cy) = bucket_of(point);
+
+        // rings of buckets closer than `first` don't reach any of the buckets in use, and rings further than `last` are past all of them
+        let first = (lx - cx).max(cx - hx).max(ly - cy).max(cy - hy).max(0);
+        let last = (hx - cx).max(cx - lx).max(hy - cy).max(cy - ly);
+
+        let mut best: Option<(isize, ByRisk)> = None;
+        for ring in first..=last {
+            // every point in this ring of buckets is at least this far away (along one axis)
+            let min_distance = ((ring - 1) * BUCKET_SIZE).max(0);
+            if best.is_some_and(|(d, _)| min_distance.pow(2) > d) {
+                break;
+            }
+            // the four edges of the ring, cut down to the buckets in use
+            let edges = if ring == 0 { 1 } else { 2 };
+            let (x0, x1) = ((cx - ring).max(lx), (cx + ring).min(hx));
+            let (y0, y1) = ((cy - ring + 1).max(ly), (cy + ring - 1).min(hy));
+            let rows = [cy - ring, cy + ring].into_iter().take(edges).filter(|by| (ly..=hy).contains(by)).flat_map(|by| (x0..=x1).map(move |bx| (bx, by)));
+            let columns = [cx - ring, cx + ring].into_iter().take(edges).filter(|bx| (lx..=hx).contains(bx)).flat_map(|bx| (y0..=y1).map(move |by| (bx, by)));
+            for b in rows.chain(columns) {
+                let Some(bucket) = self.buckets.get(&b) else { continue };
+                for &entry in bucket.iter().take_while(|e| e.1 <= max_risk) {
+                    let d = distance_squared(point, entry.0);
+                    if best.is_none_or(|(bd, be)| (d, entry.0) < (bd, be.0)) {
+                        best = Some((d, entry));
+                    }
+                }
+            }
+        }
+        best.map(|(_, ByRisk(p, r))| (p, r))
+    }
 }
 
 impl From<HashMap<Coord, f32>> for RiskCache {
     fn from(contents: HashMap<Coord, f32>) -> Self {
-        Self { by_risk: contents.iter().map(|(&point, &risk)| ByRisk(point, risk)).collect(), contents }
+        let mut cache = Self { by_risk: contents.iter().map(|(&point, &risk)| ByRisk(point, risk)).collect(), contents, ..Default::default() };
+        cache.reindex();
+        cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+    use super::*;
+
+    fn random_cache() -> RiskCache {
+        let mut rng = rand::rng();
+        let mut point = || (rng.random_range(-100..100i32) as isize, rng.random_range(-100..100i32) as isize);
+        let mut cache = RiskCache::new();
+        for _ in 0..2000 {
+            cache.insert(point(), (point().0 % 5).abs() as f32 / 4.0);
+        }
+        for _ in 0..500 {
+            cache.remove(point());
+        }
+        cache
+    }
+
+    #[test]
+    fn rect_queries() {
+        let cache = random_cache();
+        let (low, high) = ((-37, 5), (20, 61));
+        let inside = |&((x, y), _): &(Coord, f32)| low.0 <= x && x < high.0 && low.1 <= y && y < high.1;
+
+        let mut expected: Vec<_> = cache.iter().filter(inside).collect();
+        let mut found: Vec<_> = cache.in_rect(low, high).collect();
+        expected.sort_by_key(|&(p, _)| p);
+        found.sort_by_key(|&(p, _)| p);
+        assert_eq!(found, expected);
+
+        let best = expected.iter().map(|&(_, r)| r).fold(1.0, f32::min);
+        assert_eq!(cache.best_in_rect(low, high).map(|(_, r)| r), Some(best));
+        assert_eq!(cache.best_in_rect(low, low), None);
+    }
+
+    #[test]
+    fn nearest_query() {
+        let cache = random_cache();
+        for point in [(0, 0), (-250, 13), (99, -99)] {
+            for max_risk in [0.0, 0.5, 1.0] {
+                let expected = cache.iter().filter(|&(_, r)| r <= max_risk).map(|(p, _)| distance_squared(point, p)).min();
+                assert_eq!(cache.nearest(point, max_risk).map(|(p, _)| distance_squared(point, p)), expected);
+            }
+        }
+        assert_eq!(RiskCache::new().nearest((0, 0), 1.0), None);
+    }
+
+    #[test]
+    fn nearest_far_away() {
+        let cache = random_cache();
+        let point = (1 << 30, -(1 << 30));
+        let expected = cache.iter().filter(|&(_, r)| r <= 0.5).map(|(p, _)| distance_squared(point, p)).min();
+        assert_eq!(cache.nearest(point, 0.5).map(|(p, _)| distance_squared(point, p)), expected);
+
+        // nothing is safe enough, so there's nothing to find no matter how far out we look
+        let mut cache = RiskCache::new();
+        for x in 0..100 {
+            cache.insert((x * 37, x * -53), 0.5);
+        }
+        assert_eq!(cache.nearest(point, 0.25), None);
+        assert_eq!(cache.nearest((0, 0), 0.25), None);
     }
 }
@@ -0,0 +1,160 @@
+//! Mine density that changes across the board.
+//!
+//! A density map is written as lines of `key value`, the same as the header of the text format, with `#` starting a comment:
+//!
+//! ```text
+//! # gets harder by 0.002 for every cell away from the origin, up to 0.35
+//! ramp 0.002 0.35
+//! # but the first few cells out are easy
+//! zone -5 -5 5 5 0.1
+//! ```
+
+use savefile::prelude::Savefile;
+use std::fmt;
+
+/// Density that goes up the further a cell is from the origin, counting diagonal steps as one.
+#[derive(Clone, Copy, Debug, PartialEq, Savefile)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ramp {
+    /// How much the density goes up for each step away.
+    pub rate: f32,
+    /// The density never ramps any higher than this.
+    pub max: f32,
+}
+
+/// A rectangle of cells with its own density. Both corners are part of the zone.
+#[derive(Clone, Copy, Debug, PartialEq, Savefile)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Zone {
+    pub from: (isize, isize),
+    pub to: (isize, isize),
+    pub density: f32,
+}
+
+impl Zone {
+    fn contains(&self, (x, y): (isize, isize)) -> bool {
+        self.from.0 <= x && x <= self.to.0 && self.from.1 <= y && y <= self.to.1
+    }
+}
+
+/// How the density of a board changes from place to place. The default map leaves it the same everywhere.
+#[derive(Clone, Debug, PartialEq, Default, Savefile)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DensityMap {
+    pub ramp: Option<Ramp>,
+    /// Cells in a zone ignore the ramp. Where zones overlap, the first one wins.
+    pub zones: Vec<Zone>,
+}
+
+fn is_density(d: f32) -> bool {
+    (0.0..=1.0).contains(&d)
+}
+
+impl DensityMap {
+    /// Read a map from text, in the format described at the top of this module.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut map = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').ok_or_else(|| format!("line {}: expected a key and a value", i + 1))?;
+            map.add_line(key, value.trim()).map_err(|e| format!("line {}: {e}", i + 1))?;
+        }
+        Ok(map)
+    }
+
+    /// Add a `ramp` or `zone` line to the map.
+    pub(crate) fn add_line(&mut self, key: &str, value: &str) -> Result<(), &'static str> {
+        let numbers: Vec<_> = value.split_whitespace().collect();
+        match key {
+            "ramp" => {
+                let [rate, max] = numbers[..] else { return Err("expected a rate and a maximum") };
+                let ramp = Ramp { rate: rate.parse().map_err(|_| "invalid rate")?, max: max.parse().map_err(|_| "invalid maximum")? };
+                self.ramp = Some(ramp);
+            },
+            "zone" => {
+                let [x1, y1, x2, y2, density] = numbers[..] else { return Err("expected two corners and a density") };
+                let corner = |x: &str, y: &str| Some((x.parse().ok()?, y.parse().ok()?));
+                let from = corner(x1, y1).ok_or("invalid corner")?;
+                let to = corner(x2, y2).ok_or("invalid corner")?;
+                self.zones.push(Zone { from, to, density: density.parse().map_err(|_| "invalid density")? });
+            },
+            _ => return Err("unknown key"),
+        }
+        self.validate()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        if self.ramp.is_some_and(|r| !(r.rate.is_finite() && r.rate >= 0.0 && is_density(r.max))) {
+            return Err("the ramp is out of range");
+        }
+        for zone in &self.zones {
+            if !is_density(zone.density) {
+                return Err("a zone's density is out of range");
+            }
+            if zone.from.0 > zone.to.0 || zone.from.1 > zone.to.1 {
+                return Err("a zone's corners are the wrong way around");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_uniform(&self) -> bool {
+        self.ramp.is_none_or(|r| r.rate == 0.0) && self.zones.is_empty()
+    }
+
+    /// The density at a point, given the density of the board as a whole.
+    pub fn at(&self, base: f32, point: (isize, isize)) -> f32 {
+        if let Some(zone) = self.zones.iter().find(|z| z.contains(point)) {
+            return zone.density;
+        }
+        match self.ramp {
+            Some(Ramp { rate, max }) => {
+                let distance = point.0.unsigned_abs().max(point.1.unsigned_abs());
+                (base + rate * distance as f32).min(max.max(base))
+            },
+            None => base,
+        }
+    }
+}
+
+/// Writes the lines that `DensityMap::parse` reads, which is nothing at all for the default map.
+impl fmt::Display for DensityMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(Ramp { rate, max }) = self.ramp {
+            writeln!(f, "ramp {rate} {max}")?;
+        }
+        for Zone { from: (x1, y1), to: (x2, y2), density } in &self.zones {
+            writeln!(f, "zone {x1} {y1} {x2} {y2} {density}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn densities() {
+        let map = DensityMap::parse("# comment\nramp 0.01 0.3\n\nzone -1 -1 1 1 0 # safe\nzone 0 0 20 0 0.9\n").unwrap();
+        assert_eq!(map.at(0.2, (0, 0)), 0.0);
+        assert_eq!(map.at(0.2, (2, 0)), 0.9);
+        assert_eq!(map.at(0.2, (-5, 3)), 0.25);
+        assert_eq!(map.at(0.2, (-100, 3)), 0.3);
+        // the ramp never takes the density down
+        assert_eq!(map.at(0.5, (-100, 3)), 0.5);
+        assert!(!map.is_uniform());
+        assert_eq!(DensityMap::parse(&map.to_string()), Ok(map));
+
+        assert!(DensityMap::default().is_uniform());
+        assert_eq!(DensityMap::default().to_string(), "");
+        assert!(DensityMap::parse("ramp 0.1").is_err());
+        assert!(DensityMap::parse("ramp -0.1 0.5").is_err());
+        assert!(DensityMap::parse("zone 0 0 1 1 2").is_err());
+        assert!(DensityMap::parse("zone 1 0 0 1 0.5").is_err());
+        assert!(DensityMap::parse("slope 1").is_err());
+    }
+}
@@ -1,25 +1,36 @@
 use savefile::prelude::Savefile;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZeroUsize;
 use std::time::Duration;
 
-use crate::judges::Judge;
+use crate::audit::Decision;
+use crate::bias::Bias;
+use crate::judges::{Judge, RuleChange, Tolerance};
 use crate::cache::RiskCache;
-use crate::saving::legacy;
+use crate::density::DensityMap;
+use crate::solver::Memo;
+use crate::regions::Regions;
+use crate::saving::legacy::{self, Judge as LegacyJudge, JudgeV9};
+use crate::shape::{Mask, Shape};
+use crate::topology::Topology;
 
 #[derive(Clone, Copy, Savefile)]
 #[repr(C)]
-struct CellData {
+pub(crate) struct CellData {
     /* bit-packed representation:
        x   x   x   x   x   x   x   x
-       -------------           |   -
-       mine count              |   revealed?
-                               |
-                               flagged by player?
+       -------------   -----   |   -
+       mine count      |       |   revealed?
+                       |       |
+                       |       flagged by player?
+                       |
+                       high bits of the mine count, for revealed cells in topologies with more than 15 neighbours
     */
-    data: u8,
+    pub(crate) data: u8,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
 pub enum Cell {
     Hidden(bool),
     Revealed(u8),
@@ -43,7 +54,7 @@ impl Cell {
         CellData {
             data: match self {
                 Self::Hidden(p) => (p as u8) << 1,
-                Self::Revealed(n) => n << 4 | 1,
+                Self::Revealed(n) => (n & 15) << 4 | (n >> 4) << 2 | 1,
             },
         }
     }
@@ -52,7 +63,7 @@ impl Cell {
 impl CellData {
     fn to_cell(self) -> Cell {
         if self.data & 1 == 1 {
-            Cell::Revealed(self.data >> 4)
+            Cell::Revealed(self.data >> 4 | (self.data >> 2 & 3) << 4)
         } else {
             Cell::Hidden(self.data >> 1 & 1 == 1)
         }
@@ -67,11 +78,28 @@ impl From<legacy::CellData> for CellData {
 }
 
 pub(crate) type Coord = (isize, isize);
-const CHUNK_SIZE: isize = 64;
-const CHUNK_AREA: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+pub(crate) const CHUNK_SIZE: isize = 64;
+pub(crate) const CHUNK_AREA: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
 
-pub fn adjacents((x, y): Coord) -> impl Iterator<Item=Coord> {
-    [(x, y-1), (x+1, y-1), (x+1, y), (x+1, y+1), (x, y+1), (x-1, y+1), (x-1, y), (x-1, y-1)].into_iter()
+/// The 8 cells around a point. `Field::adjacents` takes the topology of the board into account.
+pub fn adjacents(point: Coord) -> impl Iterator<Item=Coord> {
+    Topology::Moore.adjacents(point)
+}
+
+/// The top-left cell of a bounded board of the given size.
+pub(crate) fn top_left((width, height): (usize, usize)) -> Coord {
+    ((-(width as isize)).div_euclid(2), (-(height as isize)).div_euclid(2))
+}
+
+/// Move a point onto a board of the given size that wraps around, or leave it alone if the board doesn't.
+fn wrap_onto(wrap: Option<(usize, usize)>, (x, y): Coord) -> Coord {
+    match wrap {
+        Some(size@(width, height)) => {
+            let (lx, ly) = top_left(size);
+            (lx + (x - lx).rem_euclid(width as isize), ly + (y - ly).rem_euclid(height as isize))
+        },
+        None => (x, y),
+    }
 }
 
 fn chunk_point((x, y): Coord) -> (Coord, usize) {
@@ -82,14 +110,62 @@ fn chunk_point((x, y): Coord) -> (Coord, usize) {
 
 #[derive(Savefile, Clone)]
 pub struct Field {
-    chunks: HashMap<Coord, [CellData; CHUNK_AREA]>,
+    pub(crate) chunks: HashMap<Coord, [CellData; CHUNK_AREA]>,
     pub(crate) risk_cache: RiskCache,
     pub(crate) density: f32,
-    pub judge: Judge,
+    #[savefile_versions_as = "0..8:LegacyJudge"]
+    #[savefile_versions_as = "9..10:JudgeV9"]
+    #[savefile_versions = "11.."]
+    pub(crate) judge: Judge,
     pub(crate) solvable: bool,
-    size: Option<(usize, usize)>,
-    cells_revealed: usize,
+    pub(crate) size: Option<(usize, usize)>,
+    pub(crate) cells_revealed: usize,
     time_elapsed: Duration,
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) threads: Option<NonZeroUsize>,
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) memo: Memo,
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) regions: Regions,
+    /// Which cells inside `size` are part of the board, if not all of them. Only saved since version 4.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) mask: Option<Mask>,
+    /// Whether the edges of the board wrap around to the other side. Only saved since version 5.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) wrapping: bool,
+    /// Only saved since version 6.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) topology: Topology,
+    /// Only saved since version 7.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) density_map: DensityMap,
+    /// Only saved since version 8.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) rule_history: Vec<RuleChange>,
+    /// Only saved since version 10.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) bias: Bias,
+    /// Only saved since version 11.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) tolerance: Tolerance,
+    /// Only saved since version 12.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) seed: Option<u64>,
+    /// The judge's decisions, if they're being logged. Never saved.
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub(crate) audit_log: Option<VecDeque<Decision>>,
 }
 
 impl From<legacy::Field> for Field {
@@ -103,6 +179,18 @@ impl From<legacy::Field> for Field {
             solvable: old.solvable,
             size: None,
             time_elapsed: Duration::ZERO,
+            threads: None,
+            memo: Memo::new(),
+            regions: Regions::default(),
+            mask: None,
+            wrapping: false,
+            topology: Topology::Moore,
+            density_map: DensityMap::default(),
+            rule_history: Vec::new(),
+            bias: Bias::None,
+            tolerance: Tolerance::default(),
+            seed: None,
+            audit_log: None,
         }
     }
 }
@@ -115,16 +203,42 @@ impl Field {
             density, judge, solvable, size,
             cells_revealed: 0,
             time_elapsed: Duration::ZERO,
+            threads: None,
+            memo: Memo::new(),
+            regions: Regions::default(),
+            mask: None,
+            wrapping: false,
+            topology: Topology::Moore,
+            density_map: DensityMap::default(),
+            rule_history: Vec::new(),
+            bias: Bias::None,
+            tolerance: Tolerance::default(),
+            seed: None,
+            audit_log: None,
         }
     }
 
-    fn in_bounds(&self, point: Coord) -> bool {
-        self.size.is_none_or(|(width, height)| {
+    pub(crate) fn in_bounds(&self, point: Coord) -> bool {
+        self.size.is_none_or(|size@(width, height)| {
             let (width, height) = (width as isize, height as isize);
-            (-width).div_euclid(2) <= point.0 && point.0 < width / 2 && (-height).div_euclid(2) <= point.1 && point.1 < height / 2
+            let (lx, ly) = top_left(size);
+            lx <= point.0 && point.0 < width / 2 && ly <= point.1 && point.1 < height / 2
+                && self.mask.as_ref().is_none_or(|m| m.contains((point.0 - lx) as usize, (point.1 - ly) as usize))
         })
     }
 
+    /// Where a point is on the board. On a board that wraps, every point corresponds to one inside of the bounds.
+    /// Otherwise, points are left as they are.
+    pub fn wrap(&self, point: Coord) -> Coord {
+        wrap_onto(self.size.filter(|_| self.wrapping), point)
+    }
+
+    /// The cells next to a point in the topology of the board, wrapping around the edges of the board if it wraps.
+    pub fn adjacents(&self, point: Coord) -> impl Iterator<Item=Coord> + 'static {
+        let wrap = self.size.filter(|_| self.wrapping);
+        self.topology.adjacents(point).map(move |p| wrap_onto(wrap, p))
+    }
+
     pub fn get(&self, point: Coord) -> Option<Cell> {
         if !self.in_bounds(point) { return None; }
         let (chunk_coord, idx) = chunk_point(point);
@@ -134,9 +248,23 @@ impl Field {
         })
     }
 
+    /// Every cell that isn't in the default state, i.e. every cell that has been revealed or flagged.
+    pub fn cells(&self) -> impl Iterator<Item=(Coord, Cell)> {
+        self.chunks.iter().flat_map(|(&(cx, cy), chunk)| chunk.iter().enumerate().filter_map(move |(i, data)| {
+            let cell = data.to_cell();
+            let point = (cx*CHUNK_SIZE + i as isize % CHUNK_SIZE, cy*CHUNK_SIZE + i as isize / CHUNK_SIZE);
+            (cell != Cell::default()).then_some((point, cell))
+        }))
+    }
+
     pub fn clear(&mut self) {
         self.chunks.clear();
+        self.rule_history.clear();
+        if let Some(log) = &mut self.audit_log {
+            log.clear();
+        }
         self.risk_cache.clear();
+        self.regions = Regions::default();
         self.cells_revealed = 0;
     }
 
@@ -154,14 +282,101 @@ impl Field {
         }
     }
 
+    /// The density of the board as a whole. See `density_at` for the density of a particular cell.
     pub fn density(&self) -> f32 {
         self.density
     }
 
+    /// The chance of a cell being a mine before anything is known about it.
+    pub fn density_at(&self, point: Coord) -> f32 {
+        self.density_map.at(self.density, point)
+    }
+
+    /// Change how the density varies across the board. Like `set_topology`, this should happen before anything is revealed.
+    pub fn set_density_map(&mut self, map: DensityMap) {
+        self.density_map = map;
+    }
+
+    pub fn density_map(&self) -> &DensityMap {
+        &self.density_map
+    }
+
+    pub fn solvable(&self) -> bool {
+        self.solvable
+    }
+
+    /// Make revealed cells prefer some numbers over others.
+    pub fn set_bias(&mut self, bias: Bias) {
+        self.bias = bias;
+    }
+
+    pub fn bias(&self) -> Bias {
+        self.bias
+    }
+
+    /// Make every random choice depend only on the seed and the cell being revealed, so that fields with the same seed and settings
+    /// turn out the same when they're played the same way. Without a seed, every field is different.
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn size(&self) -> Option<(usize, usize)> {
+        self.size
+    }
+
+    /// Change the shape of the board. Masks set the size of the board to fit them; other shapes do nothing on infinite boards.
+    pub fn set_shape(&mut self, shape: Shape) {
+        if let Some(size) = shape.size() {
+            self.size = Some(size);
+        }
+        self.mask = self.size.and_then(|size| shape.to_mask(size));
+        self.set_wrapping(self.wrapping);
+    }
+
+    /// Make the edges of a bounded board wrap around, so that cells on opposite edges are next to each other.
+    /// Small boards can't wrap, since some cells would be next to each other twice: they need to be at least 3x3, or 5x5 in topologies
+    /// where neighbours can be 2 cells away. Hex boards also need an even number of rows.
+    pub fn set_wrapping(&mut self, wrapping: bool) {
+        self.wrapping = wrapping && self.size.is_some_and(|size| self.topology.can_wrap(size));
+    }
+
+    pub fn wraps(&self) -> bool {
+        self.wrapping
+    }
+
+    /// Change which cells count as next to each other. This should happen before anything is revealed, since the numbers on the board
+    /// aren't recounted.
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+        self.set_wrapping(self.wrapping);
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.mask.as_ref().map_or(Shape::Rectangle, |m| Shape::Mask(m.rows()))
+    }
+
+    /// The number of cells on a bounded board.
+    pub fn area(&self) -> Option<usize> {
+        self.size.map(|(width, height)| self.mask.as_ref().map_or(width*height, Mask::area))
+    }
+
     pub fn risks(&self) -> &RiskCache {
         &self.risk_cache
     }
 
+    /// Set how many threads the solver may use for large groups. `None` uses all available parallelism.
+    pub fn set_threads(&mut self, threads: Option<NonZeroUsize>) {
+        self.threads = threads;
+    }
+
     pub fn pass_time(&mut self, time: Duration) {
         self.time_elapsed += time;
     }
@@ -175,7 +390,7 @@ impl Field {
     }
 
     pub fn is_won(&self) -> bool {
-        self.size.is_some_and(|(width, height)| width*height == self.cells_revealed + self.risk_cache.len() && !self.has_safe())
+        self.area().is_some_and(|area| area == self.cells_revealed + self.risk_cache.len() && !self.has_safe())
     }
 }
 
@@ -191,7 +406,7 @@ mod tests {
 
     #[test]
     fn cell_conversion() {
-        for cell in [Cell::Hidden(true), Cell::Revealed(3)] {
+        for cell in [Cell::Hidden(true), Cell::Hidden(false), Cell::Revealed(3), Cell::Revealed(16), Cell::Revealed(24)] {
             assert_eq!(cell.to_data().to_cell(), cell);
         }
     }
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::field::*;
+
+/// Something wrong with a field that shouldn't be possible in normal play.
+/// Risks outside of 0 to 1 aren't among them, as loading a save with one fails, and the risk cache's indices are rebuilt when loading.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// The stored count of revealed cells doesn't match the cells themselves.
+    RevealedCount { stored: usize, counted: usize },
+    /// A revealed or flagged cell outside of the bounds of the board.
+    CellOutOfBounds(Coord),
+    RiskOnRevealed(Coord),
+    RiskOutOfBounds(Coord),
+    /// A hidden cell next to a number that the solver hasn't worked out the risk of.
+    MissingRisk(Coord),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RevealedCount { stored, counted } => write!(f, "{stored} cells are recorded as revealed, but there are {counted}"),
+            Self::CellOutOfBounds(p) => write!(f, "{p:?} is outside of the board but has been played on"),
+            Self::RiskOnRevealed(p) => write!(f, "{p:?} is revealed but has a risk"),
+            Self::RiskOutOfBounds(p) => write!(f, "{p:?} is outside of the board but has a risk"),
+            Self::MissingRisk(p) => write!(f, "{p:?} is next to a number but has no risk"),
+        }
+    }
+}
+
+/// What's stored in a chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSummary {
+    /// The position of the chunk, counted in chunks rather than cells.
+    pub position: Coord,
+    pub revealed: usize,
+    pub flagged: usize,
+}
+
+/// The results of `Field::inspect`.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Every chunk that has been allocated, in order of position.
+    pub chunks: Vec<ChunkSummary>,
+    /// The number of revealed cells, counted from the chunks.
+    pub counted_revealed: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl Field {
+    /// Look for inconsistencies in the state of the field.
+    pub fn inspect(&self) -> Report {
+        let mut chunks: BTreeMap<_, _> = self.chunks.keys().map(|&p| (p, ChunkSummary { position: p, revealed: 0, flagged: 0 })).collect();
+        let mut problems = Vec::new();
+        // a cell can be next to several numbers, so don't report it more than once
+        let mut missing = BTreeSet::new();
+
+        let mut counted_revealed = 0;
+        for ((x, y), cell) in self.cells() {
+            let chunk = chunks.get_mut(&(x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE))).unwrap();
+            match cell {
+                Cell::Revealed(_) => {
+                    counted_revealed += 1;
+                    chunk.revealed += 1;
+                },
+                Cell::Hidden(_) => chunk.flagged += 1,
+            }
+            if !self.in_bounds((x, y)) {
+                problems.push(Problem::CellOutOfBounds((x, y)));
+            }
+            if matches!(cell, Cell::Revealed(n) if n > 0) {
+                for adj in self.adjacents((x, y)) {
+                    if self.get(adj).is_some_and(|c| !c.is_revealed()) && !self.risk_cache.contains_key(adj) {
+                        missing.insert(adj);
+                    }
+                }
+            }
+        }
+        problems.extend(missing.into_iter().map(Problem::MissingRisk));
+        if counted_revealed != self.cells_revealed {
+            problems.push(Problem::RevealedCount { stored: self.cells_revealed, counted: counted_revealed });
+        }
+
+        for p in self.risk_cache.keys() {
+            match self.get(p) {
+                None => problems.push(Problem::RiskOutOfBounds(p)),
+                Some(Cell::Revealed(_)) => problems.push(Problem::RiskOnRevealed(p)),
+                Some(Cell::Hidden(_)) => (),
+            }
+        }
+
+        Report { chunks: chunks.into_values().collect(), counted_revealed, problems }
+    }
+
+    /// Fix the problems that `inspect` finds by counting the revealed cells again, forgetting cells outside of the board and working
+    /// out the risk cache from scratch. Returns false if the numbers on the board contradict each other, in which case the risk
+    /// cache is left incomplete.
+    pub fn repair(&mut self) -> bool {
+        let outside: Vec<_> = self.cells().map(|(p, _)| p).filter(|&p| !self.in_bounds(p)).collect();
+        for p in outside {
+            self.set(p, Cell::default());
+        }
+        self.cells_revealed = self.cells().filter(|(_, c)| c.is_revealed()).count();
+        self.risk_cache.clear();
+        self.resolve_frontier()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::judges::Judge;
+    use super::*;
+
+    fn board(text: &str) -> Field {
+        Field::import_text(&mut text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn inspect_and_repair() {
+        let mut field = board("bounds 6x4\n\n0001##\n0012##\n012F##\n01####\n");
+        assert_eq!(field.inspect().problems, vec![]);
+        let mut healthy: Vec<_> = field.risks().iter().collect();
+        healthy.sort_by_key(|&(p, _)| p);
+
+        field.set((-3, -2), Cell::Revealed(2));
+        field.set((320, 320), Cell::Revealed(0));
+        field.cells_revealed = 16;
+        field.risk_cache.insert((-2, -2), 0.5);
+        field.risk_cache.insert((20, 0), 0.5);
+        field.risk_cache.remove((1, -1));
+
+        let report = field.inspect();
+        assert_eq!(report.counted_revealed, 14);
+        assert_eq!(report.chunks.iter().map(|c| (c.position, c.revealed, c.flagged)).collect::<Vec<_>>(), vec![
+            ((-1, -1), 6, 0),
+            ((-1, 0), 5, 0),
+            ((0, -1), 2, 0),
+            ((0, 0), 0, 1),
+            ((5, 5), 1, 0),
+        ]);
+        for problem in [
+            Problem::RevealedCount { stored: 16, counted: 14 },
+            Problem::CellOutOfBounds((320, 320)),
+            Problem::RiskOnRevealed((-2, -2)),
+            Problem::RiskOutOfBounds((20, 0)),
+            Problem::MissingRisk((1, -1)),
+        ] {
+            assert!(report.problems.contains(&problem), "{problem} wasn't found");
+        }
+
+        field.set((-3, -2), Cell::Revealed(0));
+        assert!(field.repair());
+        assert_eq!(field.inspect().problems, vec![]);
+        let mut repaired: Vec<_> = field.risks().iter().collect();
+        repaired.sort_by_key(|&(p, _)| p);
+        assert_eq!(repaired, healthy);
+    }
+
+    #[test]
+    fn unrepairable() {
+        let mut field = Field::new(0.2, Judge::Kind, false, Some((3, 1)));
+        field.set((-2, -1), Cell::Revealed(2));
+        field.set((0, -1), Cell::Revealed(0));
+        assert!(!field.repair());
+    }
+}
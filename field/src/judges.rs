@@ -1,11 +1,16 @@
 use savefile::prelude::Savefile;
-use rand::random;
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 
+use crate::audit::{Decision, Scope};
 use crate::field::*;
 use crate::saving::legacy;
 
-#[derive(Clone, Savefile)]
+#[derive(Clone, Copy, Debug, PartialEq, Savefile)]
 #[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
 pub enum Judge {
     Random,
     Strict,
@@ -13,49 +18,199 @@ pub enum Judge {
     Local,
     Global,
     #[cfg_attr(feature = "clap", clap(alias = "kaboom"))]
+    #[cfg_attr(feature = "serde", serde(alias = "kaboom"))]
     KaboomGlobal,
     KaboomLocal,
+    Evil,
+    ThresholdGlobal,
+    ThresholdLocal,
 }
 use Judge::*;
 
+impl Judge {
+    pub const ALL: [Judge; 10] = [Random, Strict, Kind, Local, Global, KaboomGlobal, KaboomLocal, Evil, ThresholdGlobal, ThresholdLocal];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Random => "random",
+            Strict => "strict",
+            Kind => "kind",
+            Local => "local",
+            Global => "global",
+            KaboomGlobal => "kaboom-global",
+            KaboomLocal => "kaboom-local",
+            Evil => "evil",
+            ThresholdGlobal => "threshold-global",
+            ThresholdLocal => "threshold-local",
+        }
+    }
+
+    /// Whether the judge uses the field's `Tolerance`.
+    pub fn has_tolerance(&self) -> bool {
+        matches!(self, ThresholdGlobal | ThresholdLocal)
+    }
+}
+
+impl fmt::Display for Judge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Judge {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "kaboom" {
+            return Ok(KaboomGlobal);
+        }
+        Self::ALL.into_iter().find(|j| j.name() == s).ok_or("unknown judge")
+    }
+}
+
+/// How much riskier than the best cell a cell can be for the threshold judges to still let it be clicked.
+#[derive(Clone, Copy, Debug, PartialEq, Savefile)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Tolerance {
+    /// A chance of being a mine added to the best risk.
+    Absolute(f32),
+    /// A fraction of the best risk added to it.
+    Relative(f32),
+}
+
+impl Tolerance {
+    fn widen(self, best: f32) -> f32 {
+        match self {
+            Self::Absolute(margin) => best + margin,
+            Self::Relative(fraction) => best * (1.0 + fraction),
+        }
+    }
+
+    pub(crate) fn is_valid(self) -> bool {
+        let (Self::Absolute(x) | Self::Relative(x)) = self;
+        x.is_finite() && x >= 0.0
+    }
+}
+
+impl Default for Tolerance {
+    /// Accept only the best risk, like the global and local judges.
+    fn default() -> Self {
+        Self::Absolute(0.0)
+    }
+}
+
+/// Absolute tolerances are written as a probability, like `0.05`, and relative ones as a percentage, like `10%`.
+impl fmt::Display for Tolerance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Absolute(margin) => write!(f, "{margin}"),
+            Self::Relative(fraction) => write!(f, "{}%", fraction * 100.0),
+        }
+    }
+}
+
+impl FromStr for Tolerance {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tolerance = match s.strip_suffix('%') {
+            Some(percent) => Self::Relative(percent.parse::<f32>().map_err(|_| "invalid tolerance")? / 100.0),
+            None => Self::Absolute(s.parse().map_err(|_| "invalid tolerance")?),
+        };
+        if !tolerance.is_valid() {
+            return Err("tolerance must be at least 0");
+        }
+        Ok(tolerance)
+    }
+}
+
+/// A change to the rules partway through a game.
+#[derive(Clone, Debug, PartialEq, Savefile)]
+pub struct RuleChange {
+    /// The judge before the change.
+    pub judge: Judge,
+    /// Whether the board was being kept solvable before the change.
+    pub solvable: bool,
+    /// The tolerance of the threshold judges before the change.
+    #[savefile_versions = "13.."]
+    pub tolerance: Tolerance,
+    /// How many cells had been revealed when the rules changed.
+    pub cells_revealed: usize,
+    pub time_elapsed: Duration,
+}
+
 impl Field {
-    fn global_clear(&self, risk: f32) -> bool {
-        risk < 1.0 && risk <= self.density && risk <= self.risk_cache.global_best()
+    pub fn judge(&self) -> Judge {
+        self.judge
     }
 
-    pub(crate) fn is_clear(&self, point: Coord) -> bool {
+    /// Change the judge, whether the board is kept solvable and how far from the best risk the threshold judges accept.
+    /// Once a game has started, the old rules are kept in `rule_history`.
+    pub fn set_rules(&mut self, judge: Judge, solvable: bool, tolerance: Tolerance) {
+        // the tolerance only changes the rules if the judge uses it
+        let changed = judge != self.judge || solvable != self.solvable || (tolerance != self.tolerance && judge.has_tolerance());
+        if changed && self.cells_revealed > 0 {
+            self.rule_history.push(RuleChange {
+                judge: self.judge,
+                solvable: self.solvable,
+                tolerance: self.tolerance,
+                cells_revealed: self.cells_revealed,
+                time_elapsed: self.time_elapsed(),
+            });
+        }
+        self.judge = judge;
+        self.solvable = solvable;
+        self.tolerance = tolerance;
+    }
+
+    /// Every time the rules have changed during this game, oldest first.
+    pub fn rule_history(&self) -> &[RuleChange] {
+        &self.rule_history
+    }
+
+    /// Whether the game hasn't been played under the same rules all the way through.
+    pub fn mixed_rules(&self) -> bool {
+        !self.rule_history.is_empty()
+    }
+
+    pub fn tolerance(&self) -> Tolerance {
+        self.tolerance
+    }
+
+    /// Decide whether a cell is clear, along with what the judge based that on.
+    pub(crate) fn decide(&self, point: Coord) -> Decision {
         let risk = self.cell_risk(point);
-        match self.judge {
-            Random => random::<f32>() > risk,
-            Kind => risk != 1.0,
-            Strict => risk == 0.0,
-            Local => {
-                if !self.risk_cache.contains_key(point) {
-                    self.global_clear(risk)
-                } else {
-                    risk != 1.0 && self.group_from(vec![point], false).into_iter().all(|c| risk <= self.risk_cache.get(c).unwrap())
-                }
+        let frontier = self.risk_cache.contains_key(point);
+        // only the threshold judges are lenient
+        let tolerance = if self.judge.has_tolerance() { self.tolerance } else { Tolerance::default() };
+        let (best, clear) = match self.judge {
+            Random => (None, self.rng_at(point, 1).random::<f32>() > risk),
+            Kind => (None, risk != 1.0),
+            // any cell that could be a mine is one
+            Strict | Evil => (None, risk == 0.0),
+            Local | ThresholdLocal if frontier => {
+                let best = self.region_best(point);
+                (Some((Scope::Region, best)), risk != 1.0 && risk <= tolerance.widen(best))
             },
-            Global => self.global_clear(risk),
-            KaboomGlobal => {
-                if risk == 1.0 {
-                    false
-                } else if risk == 0.0 {
-                    true
-                } else {
-                    self.risk_cache.contains_key(point) && self.risk_cache.global_best() > 0.0
-                }
+            Local | Global | ThresholdLocal | ThresholdGlobal => {
+                // cells nobody knows anything about count too
+                let best = self.density_at(point).min(self.risk_cache.global_best());
+                (Some((Scope::Global, best)), risk < 1.0 && risk <= tolerance.widen(best))
             },
-            KaboomLocal => {
-                if risk == 1.0 {
-                    false
-                } else if risk == 0.0 {
-                    true
-                } else {
-                    self.risk_cache.contains_key(point) && self.group_from(vec![point], false).into_iter().all(|c| self.risk_cache.get(c).unwrap() != 0.0)
-                }
+            KaboomGlobal | KaboomLocal => {
+                let best = frontier.then(|| match self.judge {
+                    KaboomGlobal => (Scope::Global, self.risk_cache.global_best()),
+                    _ => (Scope::Region, self.region_best(point)),
+                });
+                // a risky cell is only safe if there was nothing safe to click instead
+                (best, risk != 1.0 && (risk == 0.0 || best.is_some_and(|(_, best)| best > 0.0)))
             },
-        }
+        };
+        Decision { point, judge: self.judge, tolerance, risk, best, clear }
+    }
+
+    pub(crate) fn is_clear(&self, point: Coord) -> bool {
+        self.decide(point).clear
     }
 
     pub fn definite_risk(&self, point: Coord) -> Option<bool> {
@@ -76,7 +231,7 @@ impl Field {
 
     pub fn has_safe(&self) -> bool {
         match self.judge {
-            Random | Strict => self.risk_cache.global_best() == 0.0,
+            Random | Strict | Evil => self.risk_cache.global_best() == 0.0,
             _ => self.risk_cache.global_best() < 1.0,
         }
     }
@@ -99,3 +254,110 @@ impl From<legacy::Judge> for Judge {
         }
     } 
 }
+
+impl From<legacy::JudgeV9> for Judge {
+    fn from(value: legacy::JudgeV9) -> Self {
+        match value {
+            legacy::JudgeV9::Random => Random,
+            legacy::JudgeV9::Strict => Strict,
+            legacy::JudgeV9::Kind => Kind,
+            legacy::JudgeV9::Local => Local,
+            legacy::JudgeV9::Global => Global,
+            legacy::JudgeV9::KaboomGlobal => KaboomGlobal,
+            legacy::JudgeV9::KaboomLocal => KaboomLocal,
+            legacy::JudgeV9::Evil => Evil,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_changes() {
+        let mut field = Field::new(0.2, Kind, false, None);
+        // nothing has happened yet, so this is still the start of the game
+        field.set_rules(Local, false, Tolerance::default());
+        assert!(!field.mixed_rules());
+
+        field.reveal_cell_first_zero((0, 0)).unwrap();
+        field.pass_time(Duration::from_secs(5));
+        field.set_rules(Local, false, Tolerance::default());
+        assert!(!field.mixed_rules());
+        // the local judge doesn't care about the tolerance
+        field.set_rules(Local, false, Tolerance::Absolute(0.1));
+        assert!(!field.mixed_rules());
+        field.set_rules(Strict, true, Tolerance::Absolute(0.1));
+        assert_eq!(field.judge(), Strict);
+        assert!(field.solvable());
+        let cells_revealed = field.cells_revealed();
+        assert_eq!(field.rule_history(), [RuleChange { judge: Local, solvable: false, tolerance: Tolerance::Absolute(0.1), cells_revealed, time_elapsed: Duration::from_secs(5) }]);
+
+        field.set_rules(ThresholdGlobal, true, Tolerance::Absolute(0.1));
+        field.set_rules(ThresholdGlobal, true, Tolerance::Relative(0.2));
+        assert_eq!(field.tolerance(), Tolerance::Relative(0.2));
+        assert_eq!(field.rule_history().len(), 3);
+        assert_eq!(field.rule_history()[2].tolerance, Tolerance::Absolute(0.1));
+
+        let mut saved = Vec::new();
+        field.save(&mut saved).unwrap();
+        let mut loaded = Field::load(&mut &saved[..]).unwrap();
+        assert_eq!(loaded.rule_history(), field.rule_history());
+        assert_eq!(loaded.judge(), ThresholdGlobal);
+
+        loaded.clear();
+        assert!(!loaded.mixed_rules());
+    }
+
+    #[test]
+    fn evil() {
+        let import = |text: &str| Field::import_text(&mut text.as_bytes()).unwrap();
+        for _ in 0..20 {
+            // the middle cell is safe, and showing a 1 is the only way to not give away the last cell
+            let mut field = import("density 0.1\njudge evil\nbounds 3x1\n\n0##\n");
+            assert_eq!(field.reveal_cell((-1, -1)), Some(1));
+            assert_eq!(field.risks().get((0, -1)), Some(1.0));
+            assert!(field.is_won());
+        }
+
+        // nothing is certain, so everything is a mine
+        let field = import("judge evil\nbounds 3x1\n\n#1#\n");
+        assert!(!field.has_safe());
+        assert!(!field.is_clear((-2, -1)));
+
+        let mut saved = Vec::new();
+        field.save(&mut saved).unwrap();
+        assert_eq!(Field::load(&mut &saved[..]).unwrap().judge(), Evil);
+    }
+
+    #[test]
+    fn threshold() {
+        let mut field = Field::import_text(&mut "density 0.2\njudge threshold-global\nbounds 5x1\norigin -3 -1\n\n#1###\n".as_bytes()).unwrap();
+        assert_eq!(field.risks().global_best(), 0.5);
+        // the cells next to the 1 are a coin flip, so they're only clear if we're lenient enough compared to the rest of the board
+        let best = field.density();
+        assert!(!field.is_clear((-3, -1)));
+        field.set_rules(ThresholdGlobal, false, Tolerance::Absolute(0.5 - best + 0.01));
+        assert!(field.is_clear((-3, -1)));
+        field.set_rules(ThresholdGlobal, false, Tolerance::Relative(0.5 / best - 1.01));
+        assert!(!field.is_clear((-3, -1)));
+        field.set_rules(ThresholdGlobal, false, Tolerance::Relative(0.5 / best - 0.99));
+        assert!(field.is_clear((-3, -1)));
+
+        // they're the best in their region
+        field.set_rules(ThresholdLocal, false, Tolerance::default());
+        assert!(field.is_clear((-3, -1)));
+
+        let mut saved = Vec::new();
+        field.set_rules(ThresholdLocal, false, "5%".parse().unwrap());
+        field.save(&mut saved).unwrap();
+        let loaded = Field::load(&mut &saved[..]).unwrap();
+        assert_eq!(loaded.judge(), ThresholdLocal);
+        assert_eq!(loaded.tolerance(), Tolerance::Relative(0.05));
+
+        assert_eq!("0.1".parse(), Ok(Tolerance::Absolute(0.1)));
+        assert_eq!(Tolerance::Relative(0.25).to_string(), "25%");
+        assert!("-5%".parse::<Tolerance>().is_err());
+    }
+}
@@ -2,8 +2,28 @@ mod field;
 mod judges;
 mod solver;
 mod cache;
+mod regions;
+mod inspect;
+mod shape;
+mod topology;
+mod density;
+mod bias;
+mod audit;
+mod bots;
 mod saving;
+mod parse;
 
-pub use judges::Judge;
+pub use judges::{Judge, RuleChange, Tolerance};
 pub use field::{Cell, Field, adjacents};
 pub use cache::RiskCache;
+pub use regions::Region;
+pub use shape::Shape;
+pub use topology::Topology;
+pub use density::{DensityMap, Ramp, Zone};
+pub use bias::Bias;
+pub use audit::{Decision, Scope};
+pub use bots::{Action, Bot, Closest, LowestRisk, RandomSafe, Strategy};
+pub use inspect::{ChunkSummary, Problem, Report};
+pub use saving::LoadError;
+pub use saving::text::TextError;
+pub use parse::{parse_density, parse_size};
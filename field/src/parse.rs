@@ -0,0 +1,16 @@
+//! Parsers for settings that are written the same way by every frontend.
+
+/// A mine density, between 0 and 1.
+pub fn parse_density(s: &str) -> Result<f32, &'static str> {
+    let f = s.parse().map_err(|_| "invalid number")?;
+    if !(0.0..=1.0).contains(&f) {
+        return Err("density out of range");
+    }
+    Ok(f)
+}
+
+/// The size of a bounded board, like `30x16`.
+pub fn parse_size(s: &str) -> Result<(usize, usize), &'static str> {
+    let (width_part, height_part) = s.split_once('x').ok_or("bounds should be delimited with 'x'")?;
+    width_part.parse().ok().zip(height_part.parse().ok()).ok_or("invalid number")
+}
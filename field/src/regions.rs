@@ -0,0 +1,151 @@
+use savefile::prelude::Savefile;
+use std::collections::{HashMap, HashSet};
+use crate::field::*;
+
+/// A logical region: frontier cells that are linked together by the numbers around them without passing through a known mine.
+#[derive(Savefile, Clone)]
+pub struct Region {
+    cells: Vec<Coord>,
+    best: f32,
+}
+
+impl Region {
+    pub fn cells(&self) -> &[Coord] {
+        &self.cells
+    }
+
+    /// The lowest risk of any cell in the region.
+    pub fn best(&self) -> f32 {
+        self.best
+    }
+}
+
+/// The partition of the frontier into logical regions, which is kept up to date as cells are revealed.
+#[derive(Savefile, Clone, Default)]
+pub(crate) struct Regions {
+    ids: HashMap<Coord, usize>,
+    regions: HashMap<usize, Region>,
+    next_id: usize,
+}
+
+impl Field {
+    fn in_region(&self, point: Coord) -> bool {
+        self.risk_cache.get(point).is_some_and(|r| r != 1.0)
+    }
+
+    fn flood_region(&mut self, point: Coord) {
+        if self.regions.ids.contains_key(&point) || !self.in_region(point) {
+            return;
+        }
+
+        let mut cells = Vec::new();
+        let mut seen = HashSet::from([point]);
+        let mut stack = vec![point];
+        while let Some(p) = stack.pop() {
+            cells.push(p);
+            for adj in self.adjacents(p) {
+                if self.get(adj).is_some_and(|x| x.is_revealed() && x != Cell::Revealed(0)) {
+                    for their_adj in self.adjacents(adj) {
+                        if self.in_region(their_adj) && seen.insert(their_adj) {
+                            stack.push(their_adj);
+                        }
+                    }
+                }
+            }
+        }
+
+        let id = self.regions.next_id;
+        self.regions.next_id += 1;
+        for &c in &cells {
+            self.regions.ids.insert(c, id);
+        }
+        let best = self.risk_cache.best_of(cells.iter().copied());
+        self.regions.regions.insert(id, Region { cells, best });
+    }
+
+    /// Forget about the regions that could change when `point` is revealed, returning the cells that were in them.
+    pub(crate) fn take_regions_around(&mut self, point: Coord) -> Vec<Coord> {
+        let mut cells = Vec::new();
+        for p in self.adjacents(point).chain([point]) {
+            if let Some(id) = self.regions.ids.get(&p)
+            && let Some(region) = self.regions.regions.remove(id) {
+                for c in &region.cells {
+                    self.regions.ids.remove(c);
+                }
+                cells.extend(region.cells);
+            }
+        }
+        cells
+    }
+
+    /// Find the regions of the given cells again, after they were forgotten by `take_regions_around`.
+    pub(crate) fn refresh_regions(&mut self, cells: impl IntoIterator<Item=Coord>) {
+        for c in cells {
+            self.flood_region(c);
+        }
+    }
+
+    pub(crate) fn rebuild_regions(&mut self) {
+        self.regions = Regions::default();
+        let cells: Vec<_> = self.risk_cache.keys().collect();
+        self.refresh_regions(cells);
+    }
+
+    /// The id of the logical region containing `point`, if it's on the frontier and not a known mine.
+    /// Ids are not stable: a region gets a new id whenever it changes.
+    pub fn region_id(&self, point: Coord) -> Option<usize> {
+        self.regions.ids.get(&point).copied()
+    }
+
+    pub fn region(&self, id: usize) -> Option<&Region> {
+        self.regions.regions.get(&id)
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item=(usize, &Region)> {
+        self.regions.regions.iter().map(|(&id, region)| (id, region))
+    }
+
+    /// The lowest risk in the logical region containing `point`.
+    pub fn region_best(&self, point: Coord) -> f32 {
+        self.region_id(point).map_or(1.0, |id| self.regions.regions[&id].best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+    use super::*;
+
+    fn partition(field: &Field) -> Vec<(Vec<Coord>, u32)> {
+        let mut regions: Vec<_> = field.regions().map(|(_, r)| {
+            let mut cells = r.cells().to_vec();
+            cells.sort();
+            (cells, r.best().to_bits())
+        }).collect();
+        regions.sort();
+        regions
+    }
+
+    #[test]
+    fn incremental_regions() {
+        let mut rng = rand::rng();
+        let mut field = Field::new(0.3, crate::Judge::Kind, false, None);
+        let _ = field.reveal_cell((0, 0));
+        for _ in 0..300 {
+            let point = *field.safe_frontier().choose(&mut rng).unwrap();
+            let _ = field.reveal_cell(point);
+        }
+
+        let mut rebuilt = field.clone();
+        rebuilt.rebuild_regions();
+        assert_eq!(partition(&field), partition(&rebuilt));
+
+        for (point, _) in field.risk_cache.iter() {
+            assert_eq!(field.region_id(point).is_some(), field.in_region(point));
+            if field.in_region(point) {
+                let group = field.group_from(vec![point], false);
+                assert_eq!(field.region_best(point), field.risk_cache.best_of(group));
+            }
+        }
+    }
+}
@@ -1,27 +1,246 @@
+mod compact;
 pub mod legacy;
+pub mod text;
+#[cfg(feature = "serde")]
+mod serialize;
 
-use savefile::prelude::{load, save, SavefileError};
-use std::io::{Read, Write, Seek};
+use savefile::prelude::{load_from_mem, save, SavefileError};
+use std::fmt;
+use std::io::{self, Read, Write};
 
 use crate::field::*;
+use compact::CompactField;
 
-const VERSION: u32 = 0;
+/// Written at the start of every save since version 2, followed by the version as a little-endian `u32`.
+/// Since version 2, that's followed by the length of the rest of the save and its `checksum`, both as little-endian `u64`s.
+const MAGIC: &[u8; 8] = b"minefair";
+/// Saves from version 1 are bare `savefile` files, which start with this instead.
+const SAVEFILE_MAGIC: &[u8; 9] = b"savefile\0";
 
-impl Field {
-    pub fn load(reader: &mut (impl Read + Seek)) -> Result<Self, SavefileError> {
-        match load(reader, VERSION) {
-            Err(e) => {
-                reader.rewind()?;
-                match legacy::Field::load(reader) {
-                    Ok(x) => Ok(x.into()),
-                    Err(_) => Err(e),
-                }
-            },
-            x => x,
+/// The version of the save format written by `Field::save`.
+///
+/// * 0: the bincode format in `legacy`.
+/// * 1: `Field` written with `savefile`.
+/// * 2: the same as 1, but with a header that starts with `MAGIC`.
+/// * 3: `CompactField`, with the same header as 2.
+/// * 4: `CompactField` gained the shape of the board.
+/// * 5: `CompactField` gained whether the board wraps around.
+/// * 6: `CompactField` gained the topology of the board.
+/// * 7: `CompactField` gained the density map of the board.
+/// * 8: `CompactField` gained the history of changes to the rules.
+/// * 9: `Judge` gained the evil judge.
+/// * 10: `CompactField` gained the bias of revealed numbers.
+/// * 11: `Judge` gained the threshold judges, and `CompactField` gained their tolerance.
+/// * 12: `CompactField` gained the seed for its random choices.
+/// * 13: `RuleChange` gained the tolerance.
+///
+/// To change the format, bump this and mark what changed in `CompactField` with `#[savefile_versions]`.
+/// If the payload stops being a `CompactField`, also add a variant to `Saved` and teach `Saved::migrate` how to get to it from the one before.
+pub const VERSION: u32 = 13;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// The save ends before it should.
+    Truncated,
+    /// The save was written by a newer version of minefair.
+    TooNew { version: u32 },
+    /// The save isn't a minefair save, or it's been damaged.
+    Corrupt(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Truncated => write!(f, "the save is incomplete"),
+            Self::TooNew { version } => write!(f, "the save is from a newer version of minefair (format {version}, but only up to {VERSION} is supported)"),
+            Self::Corrupt(reason) => write!(f, "the save is corrupt ({reason})"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            Self::Truncated
+        } else {
+            Self::Io(e)
+        }
+    }
+}
+
+impl From<SavefileError> for LoadError {
+    fn from(e: SavefileError) -> Self {
+        match e {
+            SavefileError::IOError { io_error } => io_error.into(),
+            SavefileError::ShortRead => Self::Truncated,
+            e => Self::Corrupt(e.to_string()),
+        }
+    }
+}
+
+impl From<bincode::error::DecodeError> for LoadError {
+    fn from(e: bincode::error::DecodeError) -> Self {
+        match e {
+            bincode::error::DecodeError::UnexpectedEnd { .. } => Self::Truncated,
+            bincode::error::DecodeError::Io { inner, .. } => inner.into(),
+            e => Self::Corrupt(e.to_string()),
+        }
+    }
+}
+
+/// FNV-1a. Saves only need protecting from accidents, and corrupt data can make `savefile` try to allocate huge amounts of memory.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+fn read_u64(bytes: &[u8]) -> Result<(u64, &[u8]), LoadError> {
+    let (n, rest) = bytes.split_first_chunk().ok_or(LoadError::Truncated)?;
+    Ok((u64::from_le_bytes(*n), rest))
+}
+
+/// A save as it was read, in whichever format it was written in.
+enum Saved {
+    V0(legacy::Field),
+    V1(Field),
+    V2(Field),
+    /// Any version from 3 on. They're all `CompactField`s, which fill in whatever an older version is missing as they're read.
+    V3(Field),
+}
+
+impl Saved {
+    fn read(bytes: &[u8]) -> Result<Self, LoadError> {
+        if let Some(rest) = bytes.strip_prefix(MAGIC) {
+            let (version, payload) = rest.split_first_chunk().ok_or(LoadError::Truncated)?;
+            let version = u32::from_le_bytes(*version);
+            match version {
+                0 | 1 => return Err(LoadError::Corrupt(format!("format {version} never had a header"))),
+                2..=VERSION => (),
+                version => return Err(LoadError::TooNew { version }),
+            }
+            let (length, payload) = read_u64(payload)?;
+            let (sum, payload) = read_u64(payload)?;
+            let payload = payload.get(..length as usize).ok_or(LoadError::Truncated)?;
+            if checksum(payload) != sum {
+                return Err(LoadError::Corrupt("checksum doesn't match".to_owned()));
+            }
+            match version {
+                2 => Ok(Self::V2(load_from_mem(payload, VERSION)?)),
+                _ => Ok(Self::V3(load_from_mem::<CompactField>(payload, VERSION)?.try_into()?)),
+            }
+        } else if bytes.starts_with(SAVEFILE_MAGIC) {
+            Ok(Self::V1(load_from_mem(bytes, VERSION)?))
+        } else if bytes.is_empty() || MAGIC.starts_with(bytes) {
+            Err(LoadError::Truncated)
+        } else {
+            Ok(Self::V0(legacy::Field::load(&mut &bytes[..])?))
         }
     }
 
+    /// Bring a save up to the current format, one version at a time.
+    fn migrate(mut self) -> Field {
+        loop {
+            self = match self {
+                Self::V0(field) => Self::V1(field.into()),
+                // only the header changed
+                Self::V1(field) => Self::V2(field),
+                // the chunks are compressed when they're read, as there's no reason to decompress them again here
+                Self::V2(field) => Self::V3(field),
+                Self::V3(field) => return field,
+            }
+        }
+    }
+}
+
+impl Field {
+    pub fn load(reader: &mut impl Read) -> Result<Self, LoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut field = Saved::read(&bytes)?.migrate();
+        field.restore();
+        Ok(field)
+    }
+
+    /// Rebuild everything that isn't saved.
+    fn restore(&mut self) {
+        self.risk_cache.reindex();
+        self.rebuild_regions();
+    }
+
     pub fn save(&self, writer: &mut impl Write) -> Result<(), SavefileError> {
-        save(writer, VERSION, self)
+        let mut payload = Vec::new();
+        save(&mut payload, VERSION, &CompactField::from(self))?;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(payload.len() as u64).to_le_bytes())?;
+        writer.write_all(&checksum(&payload).to_le_bytes())?;
+        writer.write_all(&payload)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every fixture holds this board, saved in a different format.
+    const BOARD: &str = "density 0.25\njudge global\ntolerance 0\nsolvable false\nbias none\nseed none\ntopology moore\nbounds none\nwrap false\norigin -1 -1\n\n######\n#0001#\n#0012#\n#012F#\n#01*##\n######\n";
+
+    const FIXTURES: [&[u8]; 14] = [
+        include_bytes!("../fixtures/v0.minefair"),
+        include_bytes!("../fixtures/v1.minefair"),
+        include_bytes!("../fixtures/v2.minefair"),
+        include_bytes!("../fixtures/v3.minefair"),
+        include_bytes!("../fixtures/v4.minefair"),
+        include_bytes!("../fixtures/v5.minefair"),
+        include_bytes!("../fixtures/v6.minefair"),
+        include_bytes!("../fixtures/v7.minefair"),
+        include_bytes!("../fixtures/v8.minefair"),
+        include_bytes!("../fixtures/v9.minefair"),
+        include_bytes!("../fixtures/v10.minefair"),
+        include_bytes!("../fixtures/v11.minefair"),
+        include_bytes!("../fixtures/v12.minefair"),
+        include_bytes!("../fixtures/v13.minefair"),
+    ];
+
+    fn text_of(field: &Field) -> String {
+        let mut out = Vec::new();
+        field.export_text(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn old_versions() {
+        for (version, fixture) in FIXTURES.into_iter().enumerate() {
+            let field = Field::load(&mut &fixture[..]).unwrap_or_else(|e| panic!("failed to load version {version}: {e}"));
+            assert_eq!(text_of(&field), BOARD, "version {version} loaded wrong");
+
+            let mut saved = Vec::new();
+            field.save(&mut saved).unwrap();
+            assert_eq!(text_of(&Field::load(&mut &saved[..]).unwrap()), BOARD, "version {version} saved wrong");
+        }
+    }
+
+    #[test]
+    fn load_errors() {
+        let current = FIXTURES[VERSION as usize];
+        for fixture in FIXTURES {
+            for len in [0, 5, fixture.len() / 2, fixture.len() - 1] {
+                assert!(matches!(Field::load(&mut &fixture[..len]), Err(LoadError::Truncated)), "cut to {len} bytes");
+            }
+        }
+
+        let mut newer = current.to_vec();
+        newer[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(Field::load(&mut &newer[..]), Err(LoadError::TooNew { version }) if version == VERSION + 1));
+
+        let mut garbled = current.to_vec();
+        garbled[100] ^= 0x40;
+        assert!(matches!(Field::load(&mut &garbled[..]), Err(LoadError::Corrupt(_))));
+        assert!(matches!(Field::load(&mut &[0xff; 9][..]), Err(LoadError::Corrupt(_))));
     }
 }
+
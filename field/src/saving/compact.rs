@@ -0,0 +1,305 @@
+//! The format saves have been written in since version 3.
+//! Version 4 added the shape of the board, as a bitmask over its bounds, version 5 added whether it wraps around, version 6 added
+//! its topology, version 7 added how its density varies, version 8 added the history of changes to its rules, version 9 added the
+//! evil judge, version 10 added the bias of the numbers it reveals, version 11 added the threshold judges and their tolerance and
+//! version 12 added the seed for its random choices and version 13 added the tolerance to each change in its rule history.
+//! Older saves are read straight into the current `CompactField`, with whatever they're missing left at its default: a rectangle
+//! that doesn't wrap, the 8 cells around a cell as its neighbours, the same density everywhere, rules that never changed, unbiased
+//! numbers, no tolerance and no seed. Judges that already existed keep their numbering.
+//!
+//! Most of an infinite board is hidden, so each chunk is compressed with PackBits, a simple run-length encoding that's never
+//! much bigger than the data it encodes. Only the contents of the risk cache are written; its indices are rebuilt when loading.
+
+use savefile::prelude::Savefile;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::bias::Bias;
+use crate::cache::RiskCache;
+use crate::density::DensityMap;
+use crate::field::*;
+use crate::judges::{Judge, RuleChange, Tolerance};
+use crate::shape::Mask;
+use crate::topology::Topology;
+use super::legacy::{Judge as LegacyJudge, JudgeV9};
+use super::LoadError;
+
+#[derive(Savefile)]
+pub(super) struct CompactField {
+    chunks: Vec<(Coord, Vec<u8>)>,
+    risks: Vec<(Coord, f32)>,
+    density: f32,
+    #[savefile_versions_as = "0..8:LegacyJudge"]
+    #[savefile_versions_as = "9..10:JudgeV9"]
+    #[savefile_versions = "11.."]
+    judge: Judge,
+    solvable: bool,
+    size: Option<(usize, usize)>,
+    cells_revealed: usize,
+    time_elapsed: Duration,
+    #[savefile_versions = "4.."]
+    mask: Option<Vec<u64>>,
+    #[savefile_versions = "5.."]
+    wrapping: bool,
+    #[savefile_versions = "6.."]
+    topology: Topology,
+    #[savefile_versions = "7.."]
+    density_map: DensityMap,
+    #[savefile_versions_as = "8..8:rules_from_v8:RulesV8"]
+    #[savefile_versions_as = "9..10:rules_from_v9:RulesV9"]
+    #[savefile_versions = "11.."]
+    rule_history: Vec<RuleChange>,
+    #[savefile_versions = "10.."]
+    bias: Bias,
+    #[savefile_versions = "11.."]
+    tolerance: Tolerance,
+    #[savefile_versions = "12.."]
+    seed: Option<u64>,
+}
+
+/// A `RuleChange` as it was written in version 8, before there was an evil judge.
+#[derive(Savefile)]
+struct RuleChangeV8 {
+    judge: LegacyJudge,
+    solvable: bool,
+    cells_revealed: usize,
+    time_elapsed: Duration,
+}
+
+type RulesV8 = Vec<RuleChangeV8>;
+
+fn rules_from_v8(rules: RulesV8) -> Vec<RuleChange> {
+    rules.into_iter().map(|r| RuleChange {
+        judge: r.judge.into(),
+        solvable: r.solvable,
+        tolerance: Tolerance::default(),
+        cells_revealed: r.cells_revealed,
+        time_elapsed: r.time_elapsed,
+    }).collect()
+}
+
+/// A `RuleChange` as it was written in versions 9 and 10, before there were threshold judges.
+#[derive(Savefile)]
+struct RuleChangeV9 {
+    judge: JudgeV9,
+    solvable: bool,
+    cells_revealed: usize,
+    time_elapsed: Duration,
+}
+
+type RulesV9 = Vec<RuleChangeV9>;
+
+fn rules_from_v9(rules: RulesV9) -> Vec<RuleChange> {
+    rules.into_iter().map(|r| RuleChange {
+        judge: r.judge.into(),
+        solvable: r.solvable,
+        tolerance: Tolerance::default(),
+        cells_revealed: r.cells_revealed,
+        time_elapsed: r.time_elapsed,
+    }).collect()
+}
+
+/// Compress with PackBits. The output is a series of runs, each starting with a header byte `n`.
+/// If `n < 128`, the next `n + 1` bytes are copied as they are. Otherwise, the next byte is repeated `257 - n` times.
+fn pack(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..].iter().take(128).take_while(|&&b| b == data[i]).count();
+        if run >= 2 {
+            out.push((257 - run) as u8);
+            out.push(data[i]);
+            i += run;
+        } else {
+            // copy everything up to the next repeat
+            let start = i;
+            while i < data.len() && i - start < 128 && data.get(i + 1) != Some(&data[i]) {
+                i += 1;
+            }
+            out.push((i - start - 1) as u8);
+            out.extend_from_slice(&data[start..i]);
+        }
+    }
+    out
+}
+
+/// Undo `pack`. Fails if the data is malformed or doesn't decompress to exactly `len` bytes.
+fn unpack(packed: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut rest = packed;
+    while let Some((&n, tail)) = rest.split_first() {
+        if n < 128 {
+            let (literal, tail) = tail.split_at_checked(n as usize + 1)?;
+            out.extend_from_slice(literal);
+            rest = tail;
+        } else {
+            let (&b, tail) = tail.split_first()?;
+            out.extend(std::iter::repeat_n(b, 257 - n as usize));
+            rest = tail;
+        }
+        if out.len() > len {
+            return None;
+        }
+    }
+    (out.len() == len).then_some(out)
+}
+
+impl From<&Field> for CompactField {
+    fn from(field: &Field) -> Self {
+        Self {
+            chunks: field.chunks.iter().map(|(&p, chunk)| (p, pack(&chunk.map(|c| c.data)))).collect(),
+            risks: field.risk_cache.iter().collect(),
+            density: field.density,
+            judge: field.judge,
+            solvable: field.solvable,
+            size: field.size,
+            cells_revealed: field.cells_revealed,
+            time_elapsed: field.time_elapsed(),
+            mask: field.mask.as_ref().map(|m| m.bits().to_vec()),
+            wrapping: field.wrapping,
+            topology: field.topology,
+            density_map: field.density_map.clone(),
+            rule_history: field.rule_history.clone(),
+            bias: field.bias,
+            tolerance: field.tolerance,
+            seed: field.seed,
+        }
+    }
+}
+
+impl TryFrom<CompactField> for Field {
+    type Error = LoadError;
+
+    fn try_from(compact: CompactField) -> Result<Self, LoadError> {
+        let mut field = Field::new(compact.density, compact.judge, compact.solvable, compact.size);
+        if let Some(bits) = compact.mask {
+            let size = compact.size.ok_or_else(|| LoadError::Corrupt("an infinite board has a shape".to_owned()))?;
+            field.mask = Some(Mask::from_bits(size, bits).ok_or_else(|| LoadError::Corrupt("the shape doesn't fit the bounds".to_owned()))?);
+        }
+        field.set_topology(compact.topology);
+        compact.density_map.validate().map_err(|e| LoadError::Corrupt(e.to_owned()))?;
+        field.set_density_map(compact.density_map);
+        field.set_wrapping(compact.wrapping);
+        if compact.wrapping && !field.wrapping {
+            return Err(LoadError::Corrupt("the board is too small to wrap".to_owned()));
+        }
+        for (p, packed) in compact.chunks {
+            let data = unpack(&packed, CHUNK_AREA).ok_or_else(|| LoadError::Corrupt(format!("chunk {p:?} is malformed")))?;
+            let chunk = std::array::from_fn(|i| CellData { data: data[i] });
+            field.chunks.insert(p, chunk);
+        }
+        let neighbours = field.topology.neighbours();
+        if let Some((p, _)) = field.cells().find(|&(_, cell)| matches!(cell, Cell::Revealed(n) if n as usize > neighbours)) {
+            return Err(LoadError::Corrupt(format!("cell at {p:?} has an impossible number")));
+        }
+        if let Some((p, _)) = compact.risks.iter().find(|(_, r)| !(0.0..=1.0).contains(r)) {
+            return Err(LoadError::Corrupt(format!("risk at {p:?} is out of range")));
+        }
+        field.risk_cache = RiskCache::from(compact.risks.into_iter().collect::<HashMap<_, _>>());
+        field.cells_revealed = compact.cells_revealed;
+        field.rule_history = compact.rule_history;
+        if !compact.bias.is_valid() {
+            return Err(LoadError::Corrupt("the bias strength is out of range".to_owned()));
+        }
+        field.set_bias(compact.bias);
+        field.set_seed(compact.seed);
+        if !compact.tolerance.is_valid() {
+            return Err(LoadError::Corrupt("the tolerance is out of range".to_owned()));
+        }
+        field.tolerance = compact.tolerance;
+        field.pass_time(compact.time_elapsed);
+        Ok(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+    use super::*;
+
+    #[test]
+    fn packbits() {
+        let mut rng = rand::rng();
+        let mut cases = vec![vec![], vec![0; 4096], vec![1, 2], vec![3, 3], (0..=255).collect()];
+        for _ in 0..200 {
+            // mostly runs, like a real chunk
+            let mut data = Vec::new();
+            while data.len() < 4096 {
+                let len = if rng.random_bool(0.5) { 1 } else { rng.random_range(1..300) };
+                data.extend(std::iter::repeat_n(rng.random_range(0..4u8), len));
+            }
+            data.truncate(4096);
+            cases.push(data);
+        }
+
+        for data in cases {
+            let packed = pack(&data);
+            assert!(packed.len() <= data.len() + data.len().div_ceil(128), "packed {} bytes into {}", data.len(), packed.len());
+            assert_eq!(unpack(&packed, data.len()).as_ref(), Some(&data));
+            assert_eq!(unpack(&packed, data.len() + 1), None);
+        }
+        assert_eq!(unpack(&[5, 1, 2], 6), None);
+        assert_eq!(unpack(&[200], 57), None);
+    }
+
+    #[test]
+    fn smaller_saves() {
+        let mut field = Field::new(0.2, Judge::Kind, false, None);
+        field.reveal_cell_first_zero((0, 0)).unwrap();
+        // the kind judge lets us click anything that might be safe
+        for _ in 0..40 {
+            let (point, _) = field.risks().nearest((0, 0), field.risks().global_best()).unwrap();
+            field.reveal_cell(point).unwrap();
+        }
+
+        let mut compact = Vec::new();
+        field.save(&mut compact).unwrap();
+        let mut full = Vec::new();
+        savefile::prelude::save(&mut full, crate::saving::VERSION, &field).unwrap();
+        assert!(compact.len() * 4 < full.len(), "compact save is {} bytes, full save is {}", compact.len(), full.len());
+
+        let loaded = Field::load(&mut &compact[..]).unwrap();
+        assert_eq!(loaded.cells().collect::<HashMap<_, _>>(), field.cells().collect());
+        assert_eq!(loaded.risks().iter().collect::<HashMap<_, _>>(), field.risks().iter().collect());
+        assert_eq!(loaded.cells_revealed(), field.cells_revealed());
+    }
+
+    #[test]
+    fn shaped_saves() {
+        let mut field = Field::new(0.2, Judge::Kind, false, Some((30, 20)));
+        field.set_shape(crate::Shape::Ring(4));
+        field.set_wrapping(true);
+        field.reveal_cell_first_zero((0, -8)).unwrap();
+
+        let mut saved = Vec::new();
+        field.save(&mut saved).unwrap();
+        let loaded = Field::load(&mut &saved[..]).unwrap();
+        assert_eq!(loaded.shape(), field.shape());
+        assert_eq!(loaded.area(), field.area());
+        assert!(loaded.wraps());
+        assert_eq!(loaded.get((0, 0)), None);
+    }
+
+    #[test]
+    fn impossible_numbers() {
+        for (topology, n) in [(Topology::Moore, 63), (Topology::VonNeumann, 5)] {
+            let mut field = Field::new(0.2, Judge::Kind, false, None);
+            field.set_topology(topology);
+            field.set((3, 4), Cell::Revealed(n));
+            let mut saved = Vec::new();
+            field.save(&mut saved).unwrap();
+            assert!(matches!(Field::load(&mut &saved[..]), Err(LoadError::Corrupt(_))), "{n} was loaded in {topology}");
+        }
+    }
+
+    #[test]
+    fn bad_bias() {
+        for bias in [Bias::Generous(f32::NAN), Bias::Decisive(-1.0)] {
+            let mut field = Field::new(0.2, Judge::Kind, false, None);
+            field.set_bias(bias);
+            let mut saved = Vec::new();
+            field.save(&mut saved).unwrap();
+            assert!(matches!(Field::load(&mut &saved[..]), Err(LoadError::Corrupt(_))));
+        }
+    }
+}
@@ -1,12 +1,16 @@
 use bincode::{Encode, Decode, error::{DecodeError, EncodeError}};
+use savefile::prelude::Savefile;
 use std::collections::HashMap;
 use std::io::{Write, Read};
 
 use crate::field::*;
 
 const CHUNK_AREA: usize = 64 * 64;
+/// Far more than any real save needs, but stops a corrupt length from making us try to allocate all the memory in the world.
+const LIMIT: usize = 1 << 28;
 
-#[derive(Encode, Decode)]
+/// Also the judges that saves from before version 9 could have, since they were written with `savefile`, which needs to know how many there are.
+#[derive(Encode, Decode, Savefile)]
 pub enum Judge {
     Random,
     Strict,
@@ -17,6 +21,19 @@ pub enum Judge {
     KaboomLocal,
 }
 
+/// The judges that saves from versions 9 and 10 could have.
+#[derive(Savefile)]
+pub enum JudgeV9 {
+    Random,
+    Strict,
+    Kind,
+    Local,
+    Global,
+    KaboomGlobal,
+    KaboomLocal,
+    Evil,
+}
+
 #[derive(Clone, Copy, Encode, Decode)]
 pub struct CellData {
     pub data: u8,
@@ -33,7 +50,7 @@ pub struct Field {
 
 impl Field {
     pub fn load(reader: &mut impl Read) -> Result<Self, DecodeError> {
-        bincode::decode_from_std_read(reader, bincode::config::standard())
+        bincode::decode_from_std_read(reader, bincode::config::standard().with_limit::<LIMIT>())
     }
 
     pub fn save(&self, writer: &mut impl Write) -> Result<(), EncodeError> {
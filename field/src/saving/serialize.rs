@@ -0,0 +1,226 @@
+//! `serde` support, for tools that want to read boards without going through `savefile`.
+//!
+//! A field is written as its settings, a list of every cell that isn't hidden and unflagged, and the risk cache as a list of
+//! positions and risks. Both lists are sorted by position so the output is stable.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::Duration;
+
+use crate::bias::Bias;
+use crate::cache::RiskCache;
+use crate::density::DensityMap;
+use crate::field::*;
+use crate::judges::{Judge, RuleChange, Tolerance};
+use crate::shape::Shape;
+use crate::topology::Topology;
+
+#[derive(Serialize, Deserialize)]
+struct RiskEntry {
+    x: isize,
+    y: isize,
+    risk: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CellEntry {
+    x: isize,
+    y: isize,
+    cell: Cell,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RuleEntry {
+    judge: Judge,
+    solvable: bool,
+    #[serde(default)]
+    tolerance: Tolerance,
+    cells_revealed: usize,
+    /// In seconds.
+    time_elapsed: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FieldRepr {
+    density: f32,
+    judge: Judge,
+    #[serde(default)]
+    tolerance: Tolerance,
+    solvable: bool,
+    #[serde(default)]
+    bias: Bias,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(default)]
+    topology: Topology,
+    #[serde(default, skip_serializing_if = "DensityMap::is_uniform")]
+    density_map: DensityMap,
+    bounds: Option<(usize, usize)>,
+    /// Which cells inside the bounds are part of the board, row by row. Missing if all of them are.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mask: Option<Vec<Vec<bool>>>,
+    #[serde(default)]
+    wrap: bool,
+    /// In seconds.
+    time_elapsed: f64,
+    /// Only written for the benefit of readers. It's counted again from `cells` when loading.
+    #[serde(default)]
+    cells_revealed: usize,
+    /// The rules before each time they were changed, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rule_history: Vec<RuleEntry>,
+    cells: Vec<CellEntry>,
+    risks: RiskCache,
+}
+
+impl Serialize for RiskCache {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = self.iter().map(|((x, y), risk)| RiskEntry { x, y, risk }).collect();
+        entries.sort_by_key(|e| (e.y, e.x));
+        entries.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RiskCache {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut cache = Self::new();
+        for RiskEntry { x, y, risk } in Vec::deserialize(deserializer)? {
+            if !(0.0..=1.0).contains(&risk) {
+                return Err(D::Error::custom(format_args!("risk at ({x}, {y}) is out of range")));
+            }
+            cache.insert((x, y), risk);
+        }
+        Ok(cache)
+    }
+}
+
+impl Serialize for Field {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut cells: Vec<_> = self.cells().map(|((x, y), cell)| CellEntry { x, y, cell }).collect();
+        cells.sort_by_key(|e| (e.y, e.x));
+        FieldRepr {
+            density: self.density,
+            judge: self.judge,
+            tolerance: self.tolerance,
+            solvable: self.solvable,
+            bias: self.bias,
+            seed: self.seed,
+            topology: self.topology,
+            density_map: self.density_map.clone(),
+            bounds: self.size,
+            mask: self.mask.as_ref().map(|m| m.rows()),
+            wrap: self.wrapping,
+            time_elapsed: self.time_elapsed().as_secs_f64(),
+            cells_revealed: self.cells_revealed(),
+            rule_history: self.rule_history.iter().map(|c| RuleEntry {
+                judge: c.judge,
+                solvable: c.solvable,
+                tolerance: c.tolerance,
+                cells_revealed: c.cells_revealed,
+                time_elapsed: c.time_elapsed.as_secs_f64(),
+            }).collect(),
+            cells,
+            risks: self.risk_cache.clone(),
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = FieldRepr::deserialize(deserializer)?;
+        if !(0.0..=1.0).contains(&repr.density) {
+            return Err(D::Error::custom("density is out of range"));
+        }
+        let time = Duration::try_from_secs_f64(repr.time_elapsed).map_err(D::Error::custom)?;
+
+        let mut field = Self::new(repr.density, repr.judge, repr.solvable, repr.bounds);
+        field.set_topology(repr.topology);
+        if !repr.bias.is_valid() {
+            return Err(D::Error::custom("bias strength is out of range"));
+        }
+        field.set_bias(repr.bias);
+        field.set_seed(repr.seed);
+        if !repr.tolerance.is_valid() {
+            return Err(D::Error::custom("tolerance is out of range"));
+        }
+        field.tolerance = repr.tolerance;
+        repr.density_map.validate().map_err(D::Error::custom)?;
+        field.set_density_map(repr.density_map);
+        if let Some(rows) = repr.mask {
+            if repr.bounds.is_none_or(|(width, height)| rows.len() != height || rows.iter().any(|r| r.len() != width)) {
+                return Err(D::Error::custom("mask doesn't fit the bounds"));
+            }
+            field.set_shape(Shape::Mask(rows));
+        }
+        if field.area() == Some(0) {
+            return Err(D::Error::custom("the board has no cells in it"));
+        }
+        field.set_wrapping(repr.wrap);
+        if repr.wrap && !field.wrapping {
+            return Err(D::Error::custom("only bounded boards that are big enough can wrap"));
+        }
+        for CellEntry { x, y, cell } in repr.cells {
+            if matches!(cell, Cell::Revealed(n) if n as usize > field.topology.neighbours()) {
+                return Err(D::Error::custom(format_args!("cell at ({x}, {y}) has an impossible number")));
+            }
+            if field.get((x, y)).is_none() {
+                return Err(D::Error::custom(format_args!("cell at ({x}, {y}) is out of bounds")));
+            }
+            field.set((x, y), cell);
+        }
+        for RuleEntry { judge, solvable, tolerance, cells_revealed, time_elapsed } in repr.rule_history {
+            if !tolerance.is_valid() {
+                return Err(D::Error::custom("tolerance is out of range"));
+            }
+            let time_elapsed = Duration::try_from_secs_f64(time_elapsed).map_err(D::Error::custom)?;
+            field.rule_history.push(RuleChange { judge, solvable, tolerance, cells_revealed, time_elapsed });
+        }
+        field.risk_cache = repr.risks;
+        field.pass_time(time);
+        field.restore();
+        Ok(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let mut field = Field::new(0.3, Judge::KaboomLocal, false, Some((20, 10)));
+        field.toggle_flag((-10, -5));
+        field.reveal_cell_first_zero((0, 0)).unwrap();
+        field.pass_time(Duration::from_millis(1500));
+        field.set_rules(Judge::Global, true, Tolerance::default());
+
+        let json = serde_json::to_string(&field).unwrap();
+        let loaded: Field = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+        assert_eq!(loaded.cells_revealed(), field.cells_revealed());
+        assert_eq!(loaded.time_elapsed(), field.time_elapsed());
+        assert_eq!(loaded.rule_history(), field.rule_history());
+        assert_eq!(loaded.risks().in_rect((-10, -5), (10, 5)).count(), field.risks().len());
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["judge"], "global");
+        assert_eq!(value["rule_history"][0]["judge"], "kaboom-local");
+        assert_eq!(value["cells"][0], serde_json::json!({"x": -10, "y": -5, "cell": {"hidden": true}}));
+    }
+
+    #[test]
+    fn bad_json() {
+        let board = |cells: &str, risks: &str| format!(r#"{{"density":0.2,"judge":"kaboom","solvable":false,"bounds":[4,4],"time_elapsed":0,"cells":{cells},"risks":{risks}}}"#);
+        assert!(serde_json::from_str::<Field>(&board("[]", "[]")).is_ok());
+        assert!(serde_json::from_str::<Field>(&board(r#"[{"x":2,"y":0,"cell":{"revealed":1}}]"#, "[]")).is_err());
+        assert!(serde_json::from_str::<Field>(&board(r#"[{"x":0,"y":0,"cell":{"revealed":9}}]"#, "[]")).is_err());
+        assert!(serde_json::from_str::<Field>(&board("[]", r#"[{"x":0,"y":0,"risk":1.5}]"#)).is_err());
+
+        let shaped = |mask: &str| board("[]", "[]").replace(r#""time_elapsed""#, &format!(r#""mask":{mask},"time_elapsed""#));
+        let field = serde_json::from_str::<Field>(&shaped("[[false,true,true,true],[true,true,true,true],[true,true,true,true],[true,true,true,true]]")).unwrap();
+        assert_eq!(field.get((-2, -2)), None);
+        assert_eq!(field.area(), Some(15));
+        assert!(serde_json::from_str::<Field>(&shaped("[[true]]")).is_err());
+        assert!(serde_json::from_str::<Field>(&shaped(&format!("[{}]", ["[false,false,false,false]"; 4].join(",")))).is_err());
+    }
+}
@@ -0,0 +1,336 @@
+//! A plain text format for boards, meant for humans to read and write.
+//!
+//! A board starts with a header of `key value` lines, followed by a blank line and then the cells of the board, one row per line:
+//!
+//! ```text
+//! density 0.22
+//! judge local
+//! tolerance 0
+//! solvable false
+//! bias none
+//! seed none
+//! topology moore
+//! ramp 0.01 0.3
+//! bounds 5x3
+//! wrap false
+//! origin -2 -1
+//!
+//! 01*##
+//! 013F#
+//! 001##
+//! ```
+//!
+//! Digits are revealed cells, with `a` to `o` standing for 10 to 24 in topologies where numbers can be that high, `#` is a hidden cell, `F` is a flagged cell and `*` is a hidden cell known to be a mine.
+//! On bounded boards, `.` is a hole: a cell inside the bounds that isn't part of the board.
+//! `ramp` and `zone` lines make the density vary across the board, as described in `DensityMap`. There can be any number of `zone` lines.
+//! `wrap` makes the edges of a bounded board wrap around to the other side.
+//! `origin` gives the position of the first cell of the first row, defaulting to the top-left corner of the bounds (or `0 0` without bounds).
+//! Every other key is optional too, defaulting to the values of `Field::default`, except that `judge` defaults to `local` to match the command line.
+//! The blank line after the header can be left out, as can the header itself.
+//! Risks are not stored; they are worked out again when a board is imported.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::field::*;
+use crate::judges::Judge;
+use crate::shape::Shape;
+
+#[derive(Debug)]
+pub enum TextError {
+    Io(io::Error),
+    Syntax { line: usize, message: String },
+    /// The numbers on the board can't all be satisfied.
+    Contradiction,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            Self::Contradiction => write!(f, "the numbers on the board contradict each other"),
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+impl From<io::Error> for TextError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+fn parse_size(s: &str) -> Option<Option<(usize, usize)>> {
+    if s == "none" {
+        return Some(None);
+    }
+    let (width, height) = s.split_once('x')?;
+    Some(Some((width.parse().ok()?, height.parse().ok()?)))
+}
+
+/// Whether a line is made up only of cells, so it can't be a header line.
+fn is_row(text: &str) -> bool {
+    text.chars().all(|c| matches!(c, '0'..='9' | 'a'..='o' | '#' | 'F' | '*' | '.'))
+}
+
+impl Field {
+    /// The rectangle of cells that `export_text` writes, as the top-left and bottom-right (exclusive) corners.
+    fn text_extent(&self) -> (Coord, Coord) {
+        if let Some((width, height)) = self.size {
+            let (width, height) = (width as isize, height as isize);
+            return (((-width).div_euclid(2), (-height).div_euclid(2)), (width / 2, height / 2));
+        }
+
+        let mut lx = isize::MAX;
+        let mut hx = isize::MIN;
+        let mut ly = isize::MAX;
+        let mut hy = isize::MIN;
+        for (x, y) in self.cells().map(|(p, _)| p).chain(self.risk_cache.keys()) {
+            lx = lx.min(x);
+            hx = hx.max(x + 1);
+            ly = ly.min(y);
+            hy = hy.max(y + 1);
+        }
+        if lx > hx {
+            ((0, 0), (0, 0))
+        } else {
+            ((lx, ly), (hx, hy))
+        }
+    }
+
+    pub fn export_text(&self, writer: &mut impl Write) -> io::Result<()> {
+        let ((lx, ly), (hx, hy)) = self.text_extent();
+
+        writeln!(writer, "density {}", self.density)?;
+        writeln!(writer, "judge {}", self.judge)?;
+        writeln!(writer, "tolerance {}", self.tolerance)?;
+        writeln!(writer, "solvable {}", self.solvable)?;
+        writeln!(writer, "bias {}", self.bias)?;
+        match self.seed {
+            Some(seed) => writeln!(writer, "seed {seed}")?,
+            None => writeln!(writer, "seed none")?,
+        }
+        writeln!(writer, "topology {}", self.topology)?;
+        write!(writer, "{}", self.density_map)?;
+        match self.size {
+            Some((width, height)) => writeln!(writer, "bounds {width}x{height}")?,
+            None => writeln!(writer, "bounds none")?,
+        }
+        writeln!(writer, "wrap {}", self.wrapping)?;
+        writeln!(writer, "origin {lx} {ly}")?;
+        writeln!(writer)?;
+
+        for y in ly..hy {
+            let row: String = (lx..hx).map(|x| match self.get((x, y)) {
+                Some(Cell::Revealed(n)) => char::from_digit(n as u32, 36).unwrap(),
+                Some(Cell::Hidden(true)) => 'F',
+                Some(Cell::Hidden(false)) if self.risk_cache.get((x, y)) == Some(1.0) => '*',
+                Some(Cell::Hidden(false)) => '#',
+                None => '.',
+            }).collect();
+            writeln!(writer, "{row}")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn import_text(reader: &mut impl BufRead) -> Result<Self, TextError> {
+        let mut field = Self::default();
+        field.judge = Judge::Local;
+        let mut origin = None;
+        let mut wrap = None;
+        let mut lines = reader.lines().enumerate().map(|(i, l)| (i + 1, l));
+        let mut last_line = 0;
+        // header lines always have a space in them, so the blank line after the header can be left out
+        let mut first_row = None;
+
+        for (line, text) in lines.by_ref() {
+            last_line = line;
+            let text = text?;
+            let syntax = |message: &str| TextError::Syntax { line, message: message.to_owned() };
+            if text.trim().is_empty() {
+                break;
+            }
+            if is_row(&text) {
+                first_row = Some((line, Ok(text)));
+                break;
+            }
+            let (key, value) = text.trim().split_once(' ').ok_or_else(|| syntax("expected a key and a value"))?;
+            let value = value.trim();
+            match key {
+                "density" => field.density = value.parse().ok().filter(|d| (0.0..=1.0).contains(d)).ok_or_else(|| syntax("invalid density"))?,
+                "judge" => field.judge = value.parse().map_err(syntax)?,
+                "solvable" => field.solvable = value.parse().map_err(|_| syntax("expected true or false"))?,
+                "tolerance" => field.tolerance = value.parse().map_err(syntax)?,
+                "bias" => field.bias = value.parse().map_err(syntax)?,
+                "seed" => field.seed = match value {
+                    "none" => None,
+                    _ => Some(value.parse().map_err(|_| syntax("expected 'none' or a whole number"))?),
+                },
+                "topology" => field.topology = value.parse().map_err(syntax)?,
+                "ramp" | "zone" => field.density_map.add_line(key, value).map_err(syntax)?,
+                "bounds" => field.size = parse_size(value).ok_or_else(|| syntax("expected 'none' or dimensions like 30x16"))?,
+                "wrap" => wrap = Some((line, value.parse().map_err(|_| syntax("expected true or false"))?)),
+                "origin" => origin = Some(value.split_once(' ')
+                    .and_then(|(x, y)| Some((x.parse().ok()?, y.trim().parse().ok()?)))
+                    .ok_or_else(|| syntax("expected two integers"))?),
+                _ => return Err(syntax("unknown key")),
+            }
+        }
+
+        if let Some((line, wrap)) = wrap {
+            field.set_wrapping(wrap);
+            if wrap && !field.wrapping {
+                return Err(TextError::Syntax { line, message: "only bounded boards that are big enough can wrap".to_owned() });
+            }
+        }
+
+        let origin = origin.unwrap_or_else(|| field.text_extent().0);
+        let mut mines = Vec::new();
+        let mut holes = Vec::new();
+        for (y, (line, text)) in (origin.1..).zip(first_row.into_iter().chain(lines)) {
+            last_line = line;
+            for (x, c) in (origin.0..).zip(text?.chars()) {
+                if field.get((x, y)).is_none() {
+                    return Err(TextError::Syntax { line, message: "cell is out of bounds".to_owned() });
+                }
+                let cell = match c {
+                    '.' if field.size.is_some() => {
+                        holes.push((x, y));
+                        continue;
+                    },
+                    '0'..='9' | 'a'..='o' => {
+                        let n = c.to_digit(36).unwrap();
+                        if n as usize > field.topology.neighbours() {
+                            return Err(TextError::Syntax { line, message: format!("{n} is more than the number of neighbours") });
+                        }
+                        Cell::Revealed(n as u8)
+                    },
+                    'F' => Cell::Hidden(true),
+                    '#' => Cell::Hidden(false),
+                    '*' => {
+                        mines.push((x, y));
+                        Cell::Hidden(false)
+                    },
+                    _ => return Err(TextError::Syntax { line, message: format!("unknown cell {c:?}") }),
+                };
+                field.set((x, y), cell);
+            }
+        }
+
+        if let Some(size@(width, height)) = field.size.filter(|_| !holes.is_empty()) {
+            let (lx, ly) = top_left(size);
+            let mut rows = vec![vec![true; width]; height];
+            for (x, y) in holes {
+                rows[(y - ly) as usize][(x - lx) as usize] = false;
+            }
+            field.set_shape(Shape::Mask(rows));
+        }
+        if field.area() == Some(0) {
+            return Err(TextError::Syntax { line: last_line, message: "the board has no cells in it".to_owned() });
+        }
+        for mine in mines {
+            field.risk_cache.insert(mine, 1.0);
+        }
+        if !field.resolve_frontier() {
+            return Err(TextError::Contradiction);
+        }
+
+        Ok(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+    use super::*;
+
+    fn import(text: &str) -> Result<Field, TextError> {
+        Field::import_text(&mut text.as_bytes())
+    }
+
+    #[test]
+    fn hand_written() {
+        let field = import("density 0.4\njudge strict\nbounds 3x2\norigin -2 -1\n\n#1#\n#1#\n").unwrap();
+        assert_eq!(field.density(), 0.4);
+        assert_eq!(field.get((-1, 0)), Some(Cell::Revealed(1)));
+        assert_eq!(field.cells_revealed(), 2);
+        for point in [(-2, -1), (0, -1), (-2, 0), (0, 0)] {
+            assert_eq!(field.risks().get(point), Some(0.25));
+        }
+
+        let field = import("bounds 4x1\n\n0#1#\n").unwrap();
+        assert_eq!(field.judge(), Judge::Local);
+        assert_eq!(field.risks().get((-1, -1)), Some(0.0));
+        assert_eq!(field.risks().get((1, -1)), Some(1.0));
+    }
+
+    #[test]
+    fn holes() {
+        let text = "density 0.22\njudge local\ntolerance 0\nsolvable false\nbias none\nseed none\ntopology moore\nbounds 3x3\nwrap false\norigin -2 -2\n\n.#.\n#1#\n.#.\n";
+        let field = import(text).unwrap();
+        assert_eq!(field.area(), Some(5));
+        assert_eq!(field.get((-2, -2)), None);
+        assert_eq!(field.risks().len(), 4);
+        assert_eq!(field.risks().get((-1, -2)), Some(0.25));
+        let mut out = Vec::new();
+        field.export_text(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), text);
+
+        // the hole doesn't need revealing
+        assert!(import("bounds 2x2\n\n*1\n.1\n").unwrap().is_won());
+        assert!(!import("bounds 2x2\n\n*1\n#1\n").unwrap().is_won());
+        assert!(matches!(import("\n.#\n"), Err(TextError::Syntax { line: 2, .. })));
+    }
+
+    #[test]
+    fn bad_boards() {
+        assert!(matches!(import("judge nonsense\n\n"), Err(TextError::Syntax { line: 1, .. })));
+        assert!(matches!(import("bounds 2x2\n\n###\n"), Err(TextError::Syntax { line: 3, .. })));
+        assert!(matches!(import("bounds 2x1\norigin -1 -1\n\n3#\n"), Err(TextError::Contradiction)));
+        assert!(matches!(import("wrap true\nbounds 2x5\n\n"), Err(TextError::Syntax { line: 1, .. })));
+        assert!(matches!(import("\n9\n"), Err(TextError::Syntax { line: 2, .. })));
+        assert!(matches!(import("topology hex\n\n7\n"), Err(TextError::Syntax { line: 3, .. })));
+        assert!(matches!(import("bounds 2x2\n\n..\n..\n"), Err(TextError::Syntax { line: 4, .. })));
+        assert!(matches!(import("bounds 0x3\n\n"), Err(TextError::Syntax { line: 2, .. })));
+        assert!(matches!(import("judge\n0\n"), Err(TextError::Syntax { line: 1, .. })));
+        assert!(matches!(import("0\nF#\n9\n"), Err(TextError::Syntax { line: 3, .. })));
+    }
+
+    #[test]
+    fn no_blank_line() {
+        for text in ["01#\n0F#\n", "origin 0 0\n01#\n0F#\n", "\n01#\n0F#\n"] {
+            let field = import(text).unwrap();
+            assert_eq!(field.get((1, 0)), Some(Cell::Revealed(1)), "{text:?}");
+            assert_eq!(field.get((1, 1)), Some(Cell::Hidden(true)), "{text:?}");
+            assert_eq!(field.cells_revealed(), 3);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = rand::rng();
+        let mut field = Field::new(0.25, Judge::Local, true, Some((20, 12)));
+        let _ = field.reveal_cell((0, 0));
+        for _ in 0..30 {
+            let Some(&point) = field.safe_frontier().choose(&mut rng) else { break };
+            let _ = field.reveal_cell(point);
+        }
+        field.toggle_flag((-10, -6));
+
+        let mut text = Vec::new();
+        field.export_text(&mut text).unwrap();
+        let imported = Field::import_text(&mut &text[..]).unwrap();
+        let mut again = Vec::new();
+        imported.export_text(&mut again).unwrap();
+        assert_eq!(String::from_utf8(text).unwrap(), String::from_utf8(again).unwrap());
+
+        for (point, risk) in field.risks().iter() {
+            let other = imported.risks().get(point).unwrap();
+            assert!((risk - other).abs() < 1e-4, "{point:?}: {risk} became {other}");
+        }
+        assert_eq!(field.risks().len(), imported.risks().len());
+    }
+}
@@ -0,0 +1,154 @@
+//! Bounded boards that aren't rectangles.
+
+/// The shape of a bounded board. Every shape fits inside the rectangle given by the board's size, centred on the origin.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum Shape {
+    #[default]
+    Rectangle,
+    /// The largest ellipse that fits in the bounds.
+    Ellipse,
+    /// An ellipse with a hole in the middle, leaving a band this many cells thick.
+    Ring(usize),
+    /// Chosen cell by cell, row by row. Cells that are `true` are part of the board.
+    /// The bounds are set to fit the mask, with short rows padded out with holes.
+    Mask(Vec<Vec<bool>>),
+}
+
+impl Shape {
+    /// Read a mask drawn in text, with `#` for cells that are part of the board and `.` or a space for holes.
+    pub fn parse_mask(text: &str) -> Result<Self, String> {
+        let mut rows = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let row = line.chars().map(|c| match c {
+                '#' => Ok(true),
+                '.' | ' ' => Ok(false),
+                c => Err(format!("line {}: unknown cell {c:?}", i + 1)),
+            }).collect::<Result<Vec<_>, _>>()?;
+            rows.push(row);
+        }
+        while rows.last().is_some_and(|r| r.is_empty()) {
+            rows.pop();
+        }
+        if !rows.iter().flatten().any(|&c| c) {
+            return Err("the mask has no cells in it".to_owned());
+        }
+        Ok(Self::Mask(rows))
+    }
+
+    /// The size of the board this shape makes, if it decides that itself.
+    pub fn size(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::Mask(rows) => Some((rows.iter().map(Vec::len).max().unwrap_or(0), rows.len())),
+            _ => None,
+        }
+    }
+
+    /// Work out which cells of a board of the given size are part of it. Returns `None` if that's all of them.
+    pub(crate) fn to_mask(&self, (width, height): (usize, usize)) -> Option<Mask> {
+        // measured from the centre of the bounds, in cells
+        let (a, b) = (width as f64 / 2.0, height as f64 / 2.0);
+        let within = move |x: usize, y: usize, a: f64, b: f64| {
+            let dx = (x as f64 + 0.5 - width as f64 / 2.0) / a;
+            let dy = (y as f64 + 0.5 - height as f64 / 2.0) / b;
+            dx*dx + dy*dy <= 1.0
+        };
+        let mask = match self {
+            Self::Rectangle => return None,
+            Self::Ellipse => Mask::new((width, height), |x, y| within(x, y, a, b)),
+            &Self::Ring(thickness) => {
+                let (ia, ib) = (a - thickness as f64, b - thickness as f64);
+                Mask::new((width, height), |x, y| within(x, y, a, b) && !(ia > 0.0 && ib > 0.0 && within(x, y, ia, ib)))
+            },
+            Self::Mask(rows) => Mask::new((width, height), |x, y| rows.get(y).and_then(|r| r.get(x)).copied().unwrap_or(false)),
+        };
+        (mask.area() < width*height).then_some(mask)
+    }
+}
+
+/// Which cells inside the bounds of a board are part of it, one bit per cell.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Mask {
+    width: usize,
+    height: usize,
+    bits: Vec<u64>,
+    area: usize,
+}
+
+impl Mask {
+    fn new((width, height): (usize, usize), contains: impl Fn(usize, usize) -> bool) -> Self {
+        let mut bits = vec![0; (width*height).div_ceil(64)];
+        for y in 0..height {
+            for x in 0..width {
+                if contains(x, y) {
+                    let i = y*width + x;
+                    bits[i / 64] |= 1 << (i % 64);
+                }
+            }
+        }
+        Self::from_bits((width, height), bits).unwrap()
+    }
+
+    /// Fails if there are the wrong number of bits for the size.
+    pub(crate) fn from_bits((width, height): (usize, usize), bits: Vec<u64>) -> Option<Self> {
+        let len = width.checked_mul(height)?;
+        if bits.len() != len.div_ceil(64) || len % 64 != 0 && bits.last().is_some_and(|&b| b >> (len % 64) != 0) {
+            return None;
+        }
+        let area = bits.iter().map(|b| b.count_ones() as usize).sum();
+        Some(Self { width, height, bits, area })
+    }
+
+    pub(crate) fn bits(&self) -> &[u64] {
+        &self.bits
+    }
+
+    /// The number of cells in the board.
+    pub(crate) fn area(&self) -> usize {
+        self.area
+    }
+
+    /// Whether a cell is part of the board, counting from the top-left corner of the bounds.
+    pub(crate) fn contains(&self, x: usize, y: usize) -> bool {
+        let i = y*self.width + x;
+        x < self.width && y < self.height && self.bits[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    pub(crate) fn rows(&self) -> Vec<Vec<bool>> {
+        (0..self.height).map(|y| (0..self.width).map(|x| self.contains(x, y)).collect()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(mask: &Mask) -> String {
+        mask.rows().iter().map(|r| r.iter().map(|&c| if c { '#' } else { '.' }).collect::<String>() + "\n").collect()
+    }
+
+    #[test]
+    fn shapes() {
+        assert_eq!(Shape::Rectangle.to_mask((5, 5)), None);
+        assert_eq!(draw(&Shape::Ellipse.to_mask((7, 5)).unwrap()), ".#####.\n#######\n#######\n#######\n.#####.\n");
+        assert_eq!(draw(&Shape::Ring(1).to_mask((7, 5)).unwrap()), ".#####.\n##...##\n#.....#\n##...##\n.#####.\n");
+        // too thick to have a hole
+        assert_eq!(Shape::Ring(4).to_mask((7, 5)), Shape::Ellipse.to_mask((7, 5)));
+
+        let mask = Shape::parse_mask("###\n# #\n#.\n\n").unwrap();
+        assert_eq!(mask.size(), Some((3, 3)));
+        let mask = mask.to_mask((3, 3)).unwrap();
+        assert_eq!(draw(&mask), "###\n#.#\n#..\n");
+        assert_eq!(mask.area(), 6);
+        assert!(!mask.contains(3, 0));
+        assert!(Shape::parse_mask("#x#").is_err());
+        assert!(Shape::parse_mask("..\n").is_err());
+    }
+
+    #[test]
+    fn bits() {
+        let mask = Shape::Ellipse.to_mask((10, 10)).unwrap();
+        assert_eq!(Mask::from_bits((10, 10), mask.bits().to_vec()), Some(mask.clone()));
+        assert_eq!(Mask::from_bits((10, 13), mask.bits().to_vec()), None);
+        assert_eq!(Mask::from_bits((10, 10), vec![!0, !0]), None);
+    }
+}
@@ -1,77 +1,349 @@
 use rand::prelude::*;
 use rand::distr::weighted::WeightedIndex;
+use std::collections::{HashMap, HashSet, BTreeMap};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 use crate::field::*;
+use crate::judges::Judge;
+use crate::bias::Bias;
+
+/// Components with at least this many unknowns are enumerated on multiple threads.
+const PARALLEL_THRESHOLD: usize = 24;
+/// How many solved components to remember before starting over.
+const MEMO_CAPACITY: usize = 4096;
+/// The highest number any topology can show.
+const MAX_NUMBER: usize = 24;
+
+/// Weights indexed by the number shown on the target cell.
+type Weights = [f64; MAX_NUMBER + 1];
+
+/// The shape of a component of a group, independent of where it is on the board.
+/// Two components with the same signature have the same solution.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Signature {
+    /// The density at each unknown, in the same order as `near_target`.
+    priors: Vec<u32>,
+    /// Whether each unknown (in the order of their positions) neighbours the target cell.
+    near_target: Vec<bool>,
+    /// The mines left to place around each number, along with the unknowns it neighbours.
+    constraints: Vec<(i8, Vec<u16>)>,
+}
+
+/// Solutions to components that have already been enumerated.
+pub(crate) type Memo = HashMap<Signature, Tally>;
+
+/// The total weight of the valid placements of mines in a group.
+#[derive(Clone)]
+pub(crate) struct Tally {
+    /// Proportion of valid placements by the number (not including unconstrained mines) they show on the target cell.
+    valid_by_num: Weights,
+    /// Like `valid_by_num`, but only counting the placements where each unknown is a mine.
+    counts: Vec<Weights>,
+}
+
+impl Tally {
+    fn new(unknowns: usize) -> Self {
+        Self { valid_by_num: [0.0; MAX_NUMBER + 1], counts: vec![[0.0; MAX_NUMBER + 1]; unknowns] }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for (x, y) in self.valid_by_num.iter_mut().zip(other.valid_by_num) {
+            *x += y;
+        }
+        for (xs, ys) in self.counts.iter_mut().zip(other.counts) {
+            for (x, y) in xs.iter_mut().zip(ys) {
+                *x += y;
+            }
+        }
+        self
+    }
+}
+
+/// `valid_by_num` for a single placement with `num` mines next to the target cell.
+fn single(num: usize) -> Weights {
+    let mut result = [0.0; MAX_NUMBER + 1];
+    result[num] = 1.0;
+    result
+}
+
+fn convolve(xs: &Weights, ys: &Weights) -> Weights {
+    let mut result = [0.0; MAX_NUMBER + 1];
+    for (i, x) in xs.iter().enumerate() {
+        // most topologies can't reach the higher numbers
+        if *x == 0.0 {
+            continue;
+        }
+        for (j, y) in ys[..=MAX_NUMBER-i].iter().enumerate() {
+            result[i+j] += x * y;
+        }
+    }
+    result
+}
+
+/// The chance of each number of mines appearing among some cells, given the chance of each one being a mine.
+fn mine_counts(priors: impl IntoIterator<Item=f64>) -> Weights {
+    let mut result = single(0);
+    for p in priors {
+        for n in (0..MAX_NUMBER).rev() {
+            result[n+1] += result[n] * p;
+            result[n] *= 1.0 - p;
+        }
+    }
+    result
+}
 
 /// A finite section of a Field, in which each revealed cell stores the number of mines and unknowns neighbouring it.
+/// Cells are numbered in order of position, and each one lists which of the others are its neighbours, so the world doesn't need to be a
+/// rectangle and can follow a board that wraps around.
+#[derive(Clone)]
 struct SmallWorld {
+    /// Has an extra cell at the end that's always `None`, standing in for neighbours outside of the world.
     marsh: Vec<Option<(i8, i8)>>,
-    ox: isize,
-    oy: isize,
-    width: usize,
-    height: usize,
+    /// Sorted, so cells can be found with a binary search.
+    positions: Vec<Coord>,
+    /// Every cell has the same number of neighbours, so the neighbours of cell `i` are `links[i*degree..(i+1)*degree]`.
+    links: Vec<usize>,
+    degree: usize,
+    /// The chance of each cell being a mine before looking at any numbers.
+    priors: Vec<f64>,
 }
 
 impl SmallWorld {
-    fn new(field: &Field, (ox, oy): Coord, (width, height): (usize, usize)) -> Self {
-        let mut marsh = Vec::with_capacity(width*height);
-
-        for y in oy..oy+height as isize {
-            for x in ox..ox+width as isize {
-                marsh.push(match field.get((x, y)) {
-                    Some(Cell::Revealed(n)) => Some((n as i8, 0)),
-                    _ => None,
-                });
-            }
-        }
+    fn new(field: &Field, cells: impl IntoIterator<Item=Coord>) -> Self {
+        let mut positions: Vec<_> = cells.into_iter().collect();
+        positions.sort();
+        positions.dedup();
+
+        let mut marsh: Vec<_> = positions.iter().map(|&p| match field.get(p) {
+            Some(Cell::Revealed(n)) => Some((n as i8, 0)),
+            _ => None,
+        }).collect();
+        marsh.push(None);
+
+        let outside = positions.len();
+        let links = positions.iter().flat_map(|&p| field.adjacents(p).map(|adj| positions.binary_search(&adj).unwrap_or(outside))).collect();
+
+        let priors = positions.iter().map(|&p| field.density_at(p) as f64).collect();
 
-        Self { marsh, ox, oy, width, height }
+        Self { marsh, positions, links, degree: field.topology.neighbours(), priors }
     }
 
     fn get_mut(&mut self, point: usize) -> &mut Option<(i8, i8)> {
         &mut self.marsh[point]
     }
 
-    fn index_of(&self, (x, y): Coord) -> Option<usize> {
-        let x = x.wrapping_sub(self.ox) as usize;
-        let y = y.wrapping_sub(self.oy) as usize;
-        (x < self.width && y < self.height).then(|| y*self.width + x)
-    }
-
-    fn index_of_unchecked(&self, (x, y): Coord) -> usize {
-        let x = (x - self.ox) as usize;
-        let y = (y - self.oy) as usize;
-        y*self.width + x
+    fn index_of_unchecked(&self, point: Coord) -> usize {
+        self.positions.binary_search(&point).unwrap()
     }
 
     fn position_of(&self, pos: usize) -> Coord {
-        ((pos % self.width) as isize + self.ox, (pos / self.width) as isize + self.oy)
+        self.positions[pos]
     }
 
     #[inline(always)]
-    fn adjacents(&self, point: usize) -> impl Iterator<Item=usize> + 'static {
-        let width = self.width;
-        [point - width - 1, point - width, point - width + 1, point - 1, point + 1, point + width - 1, point + width, point + width + 1].into_iter()
+    fn adjacents(&self, point: usize) -> impl Iterator<Item=usize> + '_ {
+        self.links[point*self.degree..(point+1)*self.degree].iter().copied().filter(move |&i| i < self.positions.len())
+    }
+
+    /// Split the unknowns into components that don't share any numbers (besides the target cell), and thus can be solved independently.
+    /// Each component is a list of indices into `unknowns`, sorted by position.
+    fn components(&self, unknowns: &[usize], target: Option<usize>) -> Vec<Vec<usize>> {
+        fn find(parent: &mut [usize], x: usize) -> usize {
+            if parent[x] != x {
+                parent[x] = find(parent, parent[x]);
+            }
+            parent[x]
+        }
+
+        let mut parent: Vec<usize> = (0..unknowns.len()).collect();
+        let mut owners = HashMap::new();
+        for (k, &i) in unknowns.iter().enumerate() {
+            for adj in self.adjacents(i) {
+                if Some(adj) != target && self.marsh[adj].is_some() {
+                    let owner = *owners.entry(adj).or_insert(k);
+                    let (a, b) = (find(&mut parent, k), find(&mut parent, owner));
+                    parent[a] = b;
+                }
+            }
+        }
+
+        let mut components: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for k in 0..unknowns.len() {
+            components.entry(find(&mut parent, k)).or_default().push(k);
+        }
+        components.into_values().map(|mut c| {
+            c.sort_by_key(|&k| self.position_of(unknowns[k]));
+            c
+        }).collect()
+    }
+
+    /// Describe a component in a way that doesn't depend on its position. `component` is a list of world indices.
+    fn signature(&self, component: &[usize], target: Option<usize>) -> Signature {
+        let near_target = component.iter().map(|&i| self.adjacents(i).any(|adj| Some(adj) == target)).collect();
+
+        let mut constraints = BTreeMap::new();
+        for (k, &i) in component.iter().enumerate() {
+            for adj in self.adjacents(i) {
+                if Some(adj) != target && let Some((n, _)) = self.marsh[adj] {
+                    constraints.entry(adj).or_insert((n, Vec::new())).1.push(k as u16);
+                }
+            }
+        }
+        let mut constraints: Vec<_> = constraints.into_values().collect();
+        constraints.sort();
+
+        let priors = component.iter().map(|&i| (self.priors[i] as f32).to_bits()).collect();
+        Signature { priors, near_target, constraints }
+    }
+
+    /// Count an unknown towards each of the numbers next to it.
+    fn add_unknown(&mut self, unknown: usize) {
+        for &j in &self.links[unknown*self.degree..(unknown+1)*self.degree] {
+            if let Some((_, u)) = &mut self.marsh[j] {
+                *u += 1;
+            }
+        }
+    }
+
+    /// Decide whether an unknown is a mine, updating its neighbours. Returns false if this makes the world invalid.
+    /// The neighbours are updated either way, so this must always be undone with `unplace`.
+    fn place(&mut self, unknown: usize, mine: bool) -> bool {
+        let mut ok = true;
+        for &j in &self.links[unknown*self.degree..(unknown+1)*self.degree] {
+            if let Some((n, u)) = &mut self.marsh[j] {
+                if mine {
+                    if *n == 0 {
+                        ok = false;
+                    }
+                    *n -= 1;
+                }
+                if u == n {
+                    ok = false;
+                }
+                *u -= 1;
+            }
+        }
+        ok
+    }
+
+    fn unplace(&mut self, unknown: usize, mine: bool) {
+        for &j in &self.links[unknown*self.degree..(unknown+1)*self.degree] {
+            if let Some((n, u)) = &mut self.marsh[j] {
+                if mine {
+                    *n += 1;
+                }
+                *u += 1;
+            }
+        }
+    }
+
+    /// Find every valid placement of mines in `unknowns[start..]`, assuming `unknowns[..start]` have already been placed according to `mines`.
+    fn enumerate(&mut self, unknowns: &[usize], mines: &mut [bool], start: usize, target: Option<usize>) -> Tally {
+        let mut tally = Tally::new(unknowns.len());
+
+        let priors: Vec<_> = unknowns.iter().map(|&u| self.priors[u]).collect();
+        let chance = |j: usize, mine: bool| if mine { priors[j] } else { 1.0 - priors[j] };
+        // chance_before[j] is the chance of the unknowns before j being placed the way they are, taking into account the density
+        let mut chance_before = vec![1.0; unknowns.len() + 1];
+        for (j, &mine) in mines[..start].iter().enumerate() {
+            chance_before[j+1] = chance_before[j] * chance(j, mine);
+        }
+
+        let mut i = start;
+        let mut stack = Vec::new();
+        let mut ok = true;
+
+        loop {
+            if ok {
+                if i == unknowns.len() {
+                    let num = target.map_or(0, |t| !(self.marsh[t].unwrap().0 as usize));
+                    let weight = chance_before[i];
+
+                    tally.valid_by_num[num] += weight;
+                    for (counts, &b) in tally.counts.iter_mut().zip(&*mines) {
+                        if b {
+                            counts[num] += weight;
+                        }
+                    }
+                } else {
+                    stack.push((false, false));
+                    stack.push((true, false));
+                }
+            }
+
+            let Some(&mut (action, ref mut done)) = stack.last_mut() else { break };
+
+            if *done {
+                stack.pop();
+                i -= 1;
+                mines[i] = false;
+                self.unplace(unknowns[i], action);
+                ok = false;
+            } else {
+                *done = true;
+                mines[i] = action;
+                chance_before[i+1] = chance_before[i] * chance(i, action);
+                ok = self.place(unknowns[i], action);
+                i += 1;
+            }
+        }
+
+        tally
+    }
+
+    /// Like `enumerate`, but splits the search tree by fixing the first few unknowns and shares the subtrees between threads.
+    fn enumerate_parallel(&self, unknowns: &[usize], target: Option<usize>, threads: usize) -> Tally {
+        let split = ((threads * 4).next_power_of_two().trailing_zeros() as usize).min(unknowns.len());
+        let next_task = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            let workers: Vec<_> = (0..threads).map(|_| s.spawn(|| {
+                let mut tally = Tally::new(unknowns.len());
+                loop {
+                    let task = next_task.fetch_add(1, Ordering::Relaxed);
+                    if task >= 1 << split {
+                        break tally;
+                    }
+
+                    let mut world = self.clone();
+                    let mut mines = vec![false; unknowns.len()];
+                    let mut ok = true;
+                    for (j, mine) in mines[..split].iter_mut().enumerate() {
+                        *mine = task >> j & 1 == 1;
+                        ok &= world.place(unknowns[j], *mine);
+                    }
+                    if ok {
+                        tally = tally.merge(world.enumerate(unknowns, &mut mines, split, target));
+                    }
+                }
+            })).collect();
+
+            workers.into_iter().map(|w| w.join().unwrap()).reduce(Tally::merge).unwrap()
+        })
     }
 }
 
 impl Field {
     pub(super) fn group_from(&self, mut stack: Vec<Coord>, cut_on_safe: bool) -> Vec<Coord> {
         let mut group = Vec::new();
+        let mut seen = HashSet::new();
 
         while let Some(p) = stack.pop() {
             let risk = self.risk_cache.get(p);
-            if group.contains(&p) || risk == Some(1.0) || cut_on_safe && risk == Some(0.0) || self.get(p).is_none_or(|x| x.is_revealed()) {
+            if seen.contains(&p) || risk == Some(1.0) || cut_on_safe && risk == Some(0.0) || self.get(p).is_none_or(|x| x.is_revealed()) {
                 continue;
             }
-            for adj in adjacents(p) {
+            for adj in self.adjacents(p) {
                 if self.get(adj).is_some_and(|x| x.is_revealed() && x != Cell::Revealed(0)) {
-                    for their_adj in adjacents(adj) {
+                    for their_adj in self.adjacents(adj) {
                         if their_adj != p {
                             stack.push(their_adj);
                         }
                     }
                 }
             }
+            seen.insert(p);
             group.push(p);
         }
 
@@ -86,45 +358,115 @@ impl Field {
         self.group_from(vec![group_candidates[0]], true).len() == group_candidates.len()
     }
 
-    fn solve_from(&mut self, point: Coord, first_zero: bool) -> u8 {
-        let mut stack: Vec<Coord> = adjacents(point).collect();
-        stack.push(point);
-        let group = self.group_from(stack, true);
+    /// Enumerate the valid placements of mines on `unknowns` one component at a time, reusing the solutions to components we've seen before.
+    /// Without a target cell, every placement is counted as showing 0.
+    fn tally(&mut self, world: &mut SmallWorld, unknowns: &[usize], target: Option<usize>) -> Tally {
+        let threads = self.threads.or_else(|| thread::available_parallelism().ok()).map_or(1, NonZeroUsize::get);
+
+        // the target cell starts off with the known mines around it already subtracted. solve each component without them,
+        // so that the solutions don't depend on anything outside of the component, and add them back on at the end
+        let before = target.map(|t| (t, world.marsh[t].unwrap()));
+        let mut known = 0;
+        if let Some((target, (n, u))) = before {
+            known = !n as usize;
+            *world.get_mut(target) = Some((!0, u));
+        }
 
-        let mut lx = isize::MAX;
-        let mut hx = isize::MIN;
-        let mut ly = isize::MAX;
-        let mut hy = isize::MIN;
-        for &(x, y) in &group {
-            lx = lx.min(x - 1);
-            hx = hx.max(x + 2);
-            ly = ly.min(y - 1);
-            hy = hy.max(y + 2);
+        let components = world.components(unknowns, target);
+        let mut solutions = Vec::with_capacity(components.len());
+        for component in &components {
+            let component: Vec<_> = component.iter().map(|&k| unknowns[k]).collect();
+            let signature = world.signature(&component, target);
+            let solution = match self.memo.get(&signature) {
+                Some(solution) => solution.clone(),
+                None => {
+                    let solution = if threads > 1 && component.len() >= PARALLEL_THRESHOLD {
+                        world.enumerate_parallel(&component, target, threads)
+                    } else {
+                        world.enumerate(&component, &mut vec![false; component.len()], 0, target)
+                    };
+                    if self.memo.len() >= MEMO_CAPACITY {
+                        self.memo.clear();
+                    }
+                    self.memo.insert(signature, solution.clone());
+                    solution
+                },
+            };
+            solutions.push(solution);
         }
 
-        let mut world = SmallWorld::new(self, (lx, ly), ((hx-lx) as usize, (hy-ly) as usize));
+        if let Some((target, before)) = before {
+            *world.get_mut(target) = Some(before);
+        }
+
+        // the components are independent apart from the number they contribute to the target cell,
+        // so the weights for the whole group are the convolution of the weights of each component
+        let mut tally = Tally::new(unknowns.len());
+        tally.valid_by_num = single(known);
+        let mut totals = Vec::with_capacity(components.len());
+        for (c, (component, solution)) in components.iter().zip(&solutions).enumerate() {
+            let others = solutions.iter().enumerate().filter(|&(d, _)| d != c).fold(single(known), |acc, (_, s)| convolve(&acc, &s.valid_by_num));
+            for (&k, counts) in component.iter().zip(&solution.counts) {
+                tally.counts[k] = convolve(counts, &others);
+            }
+            totals.push(convolve(&solution.valid_by_num, &others));
+            tally.valid_by_num = convolve(&tally.valid_by_num, &solution.valid_by_num);
+        }
+
+        // the order the convolutions happen in is different for each component, so rounding can make a cell that is always a mine
+        // look slightly less risky than that. if a count matches the total as calculated from the same component, it's exact
+        for (component, total) in components.iter().zip(totals) {
+            for &k in component {
+                for (count, (t, v)) in tally.counts[k].iter_mut().zip(total.into_iter().zip(tally.valid_by_num)) {
+                    if *count == t {
+                        *count = v;
+                    }
+                }
+            }
+        }
+
+        tally
+    }
+
+    /// Build a world big enough to solve `group`, with the known mines already subtracted from each number.
+    fn world_around(&self, group: &[Coord], target: Option<Coord>) -> SmallWorld {
+        let mut world = SmallWorld::new(self, group.iter().flat_map(|&p| self.adjacents(p).chain([p])));
 
         // The cell being clicked ("target cell") is in a quasi-state where its number is not yet known (since this function's job is to decide it),
         // so treat it like a number that starts with -1 expected mines. In the solving loop, we decrement from the expected mine count whenever
         // we add a neighbouring mine. If it is 0 before decrementing, we consider the world "invalid" and backtrack. Initializing the mine count
         // to -1 prevents this underflow from ever occurring. We can later invert the value again to retrieve the number of mines surrounding the
         // target cell. (That is, the number it should display in that world.)
-        let point_index = world.index_of_unchecked(point);
-        *world.get_mut(point_index) = Some((!0, 0));
+        if let Some(target) = target {
+            let target_index = world.index_of_unchecked(target);
+            *world.get_mut(target_index) = Some((!0, 0));
+        }
 
         // subtract already-known mines from each number
-        for y in ly-1 .. hy+1 {
-            for x in lx-1 .. hx+1 {
-                if self.risk_cache.get((x, y)) == Some(1.0) {
-                    for adj in adjacents((x, y)) {
-                        if let Some(x) = world.index_of(adj).and_then(|i| world.marsh[i].as_mut()) {
-                            x.0 -= 1;
-                        }
-                    }
-                }
+        for (&p, cell) in world.positions.iter().zip(&mut world.marsh) {
+            if let Some((n, _)) = cell {
+                *n -= self.adjacents(p).filter(|&adj| self.risk_cache.get(adj) == Some(1.0)).count() as i8;
             }
         }
 
+        world
+    }
+
+    /// The source of randomness for a choice about a cell. `stream` tells apart different choices about the same cell.
+    pub(crate) fn rng_at(&self, (x, y): Coord, stream: u64) -> StdRng {
+        let Some(seed) = self.seed else { return StdRng::from_rng(&mut rand::rng()) };
+        let mixed = [x as u64, y as u64, stream].into_iter().fold(seed, |h, v| (h ^ v).wrapping_mul(0x9e3779b97f4a7c15).rotate_left(31));
+        StdRng::seed_from_u64(mixed)
+    }
+
+    fn solve_from(&mut self, point: Coord, first_zero: bool, known: Option<u8>) -> Option<u8> {
+        let mut stack: Vec<Coord> = self.adjacents(point).collect();
+        stack.push(point);
+        let group = self.group_from(stack, true);
+
+        let mut world = self.world_around(&group, Some(point));
+        let point_index = world.index_of_unchecked(point);
+
         // collect the relevant unknowns we need to solve for
         let mut unknowns = Vec::new();
         let mut unconstrained = Vec::new();
@@ -136,181 +478,186 @@ impl Field {
             }
 
             let i = world.index_of_unchecked(pos);
-            unknowns.push((i, [0.0; 9], false));
+            unknowns.push(i);
 
             // each number needs to know how many unknowns are adjacent to it
-            for adj in world.adjacents(i) {
-                if let Some(x) = world.get_mut(adj) {
-                    x.1 += 1;
-                }
-            }
+            world.add_unknown(i);
         }
 
         // unconst_num_probs[n] is the probability of exactly n of the unconstrained cells being mines
-        // this just computes (unconstained.len() choose i) * self.density^i * (1 - self.density)^(uncontained.len() - i)
-        // AKA the binomial distribution, and there are less stupid/more accurate ways to do this, but whatever
-        let mut unconst_num_probs = [0.0; 9];
-        for n in 0..(1u32 << unconstrained.len()) {
-            let num = n.count_ones() as i32;
-            unconst_num_probs[num as usize] += self.density.powi(num) * (1.0 - self.density).powi(unconstrained.len() as i32 - num);
-        }
-
-        // proportion of valid placements by the number (not including unconstrained mines) they show on the target cell
-        let mut valid_by_num = [0.0; 9];
+        let priors: Vec<_> = unconstrained.iter().map(|&p| self.density_at(p) as f64).collect();
+        let unconst_num_probs = mine_counts(priors.iter().copied());
 
-        let mut i = 0;
-        let mut stack = Vec::new();
-        let mut ok = true;
-
-        // main solving loop
-        loop {
-            if ok {
-                if i == unknowns.len() {
-                    let mine_count = unknowns.iter().filter(|&&(_, _, v)| v).count() as i32;
-                    let num = !(world.get_mut(point_index).unwrap().0 as usize);
+        let Tally { valid_by_num, counts } = self.tally(&mut world, &unknowns, Some(point_index));
 
-                    // chance of this particular placement happening, taking into account the density
-                    let weight = self.density.powi(mine_count) * (1.0 - self.density).powi(unknowns.len() as i32 - mine_count);
+        // chance of each number appearing when the target cell is revealed
+        let num_probs = convolve(&valid_by_num, &unconst_num_probs);
+        // like `counts`, but for each unconstrained cell: the chance of it being a mine along with the rest of the cells adding up to each number
+        let unconst_by_num: Vec<_> = priors.iter().enumerate().map(|(c, &p)| {
+            let others = mine_counts(priors.iter().enumerate().filter(|&(d, _)| d != c).map(|(_, &q)| q));
+            let mut result = [0.0; MAX_NUMBER + 1];
+            result[1..].copy_from_slice(&convolve(&valid_by_num, &others)[..MAX_NUMBER]);
+            result.map(|x| x * p)
+        }).collect();
+
+        // `counts` does not take into account possible unconstrained mines yet, so fix that with the same logic as for `valid_by_num`
+        let finals: Vec<_> = counts.iter().map(|counts| convolve(counts, &unconst_num_probs)).collect();
+        // how many of the cells in the group would be known to be safe, and how many would be neither known to be safe nor known to be mines,
+        // if the target cell showed each number
+        let safe_after: [usize; MAX_NUMBER + 1] = std::array::from_fn(|num| {
+            finals.iter().chain(&unconst_by_num).filter(|f| f[num] == 0.0).count()
+        });
+        let undecided_after: [usize; MAX_NUMBER + 1] = std::array::from_fn(|num| {
+            finals.iter().chain(&unconst_by_num).filter(|f| f[num] != 0.0 && f[num] != num_probs[num]).count()
+        });
+
+        let mut weights = if self.solvable && self.risk_cache.global_best() > 0.0 && (0..=MAX_NUMBER).any(|num| num_probs[num] > 0.0 && safe_after[num] > 0) {
+            // prefer a possibility with safe cells if one exists, since there are none left
+            std::array::from_fn(|num| if safe_after[num] > 0 { num_probs[num] } else { 0.0 })
+        } else {
+            num_probs
+        };
+        if self.bias != Bias::None {
+            self.bias.apply(&mut weights, &safe_after, &undecided_after);
+        }
 
-                    valid_by_num[num] += weight;
-                    for &mut (_, ref mut counts, b) in &mut unknowns {
-                        if b {
-                            counts[num] += weight;
-                        }
-                    }
-                } else {
-                    stack.push((false, false));
-                    stack.push((true, false));
-                }
+        let num = if let Some(num) = known {
+            // the number was picked somewhere else, and all that's left is to check that it could have been
+            let num = num as usize;
+            if num_probs.get(num).is_none_or(|&p| p == 0.0) {
+                return None;
             }
-            ok = true;
+            num
+        } else if first_zero && self.risk_cache.is_empty() && num_probs[0] != 0.0 {
+            // first click always gives you a 0
+            0
+        } else if self.judge == Judge::Evil {
+            // whichever number leaves the fewest safe cells, choosing between any that tie as usual
+            let fewest = (0..=MAX_NUMBER).filter(|&num| weights[num] > 0.0).map(|num| safe_after[num]).min().unwrap();
+            let cruellest: Weights = std::array::from_fn(|num| if safe_after[num] == fewest { weights[num] } else { 0.0 });
+            WeightedIndex::new(cruellest).unwrap().sample(&mut self.rng_at(point, 0))
+        } else {
+            WeightedIndex::new(weights).unwrap().sample(&mut self.rng_at(point, 0))
+        };
 
-            let Some(&mut (action, ref mut done)) = stack.last_mut() else { break };
+        // finally just plug in risks
+        for (i, finals) in unknowns.into_iter().zip(finals) {
+            self.risk_cache.insert(world.position_of(i), (finals[num] / num_probs[num]) as f32);
+        }
 
-            if *done {
-                stack.pop();
-                i -= 1;
-                unknowns[i].2 = false;
+        for (point, weights) in unconstrained.into_iter().zip(unconst_by_num) {
+            self.risk_cache.insert(point, (weights[num] / num_probs[num]) as f32);
+        }
 
-                for j in world.adjacents(unknowns[i].0) {
-                    if let Some((n, u)) = world.get_mut(j) {
-                        if action {
-                            *n += 1;
-                        }
-                        *u += 1;
-                    }
-                }
+        Some(num as u8)
+    }
 
-                ok = false;
-            } else {
-                *done = true;
-                unknowns[i].2 = action;
-
-                for j in world.adjacents(unknowns[i].0) {
-                    if let Some((n, u)) = world.get_mut(j) {
-                        if action { 
-                            if *n == 0 {
-                                ok = false;
-                            }
-                            *n -= 1;
-                        }
-                        if u == n {
-                            ok = false;
-                        }
-                        *u -= 1;
-                    }
-                }
+    /// Solve a group from scratch, without revealing anything. Returns false if there is no way to place mines in the group.
+    fn solve_group(&mut self, group: &[Coord]) -> bool {
+        let mut world = self.world_around(group, None);
 
-                i += 1;
-            }
+        let unknowns: Vec<_> = group.iter().map(|&pos| world.index_of_unchecked(pos)).collect();
+        for &i in &unknowns {
+            world.add_unknown(i);
         }
 
-        // chance of each number appearing when the target cell is revealed
-        let mut num_probs = [0.0; 9];
-        // like valid_by_num, but for unconstrained mines
-        let mut unconst_by_num = [0.0; 9];
-
-        for (i, x) in valid_by_num.into_iter().enumerate() {
-            for (j, y) in unconst_num_probs[0..9-i].iter().enumerate() {
-                let weight = x * y;
-                num_probs[i+j] += weight;
-                unconst_by_num[i+j] += j as f32 / unconstrained.len() as f32 * weight;
-            }
+        let Tally { valid_by_num, counts } = self.tally(&mut world, &unknowns, None);
+        if valid_by_num[0] == 0.0 {
+            return false;
+        }
+        for (i, counts) in unknowns.into_iter().zip(counts) {
+            self.risk_cache.insert(world.position_of(i), (counts[0] / valid_by_num[0]) as f32);
         }
+        true
+    }
 
-        let weights = if self.solvable && self.risk_cache.global_best() > 0.0
-        // prefer a possibility with safe cells if one exists, since there are none left
-        && let safe_havers = std::array::from_fn::<_, 9, _>(|num| {
-            unknowns.iter().any(|&(_, counts, _)| {
-                for (i, x) in counts[0..=num].iter().enumerate() {
-                    if x * unconst_num_probs[num-i] != 0.0 {
-                        return false;
-                    }
+    /// Work out the risk of every hidden cell next to a number, as if none of them had been solved before.
+    /// Returns false if the numbers contradict each other.
+    pub(crate) fn resolve_frontier(&mut self) -> bool {
+        let numbers: Vec<_> = self.cells().filter_map(|(p, c)| match c {
+            Cell::Revealed(n) => Some((p, n as usize)),
+            Cell::Hidden(_) => None,
+        }).collect();
+
+        // anything that can be worked out from a single number is certain, and splits the frontier into smaller groups
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(p, n) in &numbers {
+                let hidden: Vec<_> = self.adjacents(p).filter(|&adj| self.get(adj).is_some_and(|c| !c.is_revealed())).collect();
+                let mines = hidden.iter().filter(|&&adj| self.risk_cache.get(adj) == Some(1.0)).count();
+                let undecided: Vec<_> = hidden.into_iter().filter(|&adj| !self.risk_cache.contains_key(adj)).collect();
+                if mines > n || mines + undecided.len() < n {
+                    return false;
                 }
-                true
-            }) || !unconstrained.is_empty() && unconst_by_num[num] == 0.0
-        }) && safe_havers.iter().any(|&x| x) {
-            let mut new_probs = num_probs;
-            for (i, x) in safe_havers.into_iter().enumerate() {
-                if !x {
-                    new_probs[i] = 0.0;
+                if undecided.is_empty() {
+                    continue;
                 }
+                if mines == n {
+                    undecided.into_iter().for_each(|adj| self.risk_cache.insert(adj, 0.0));
+                } else if mines + undecided.len() == n {
+                    undecided.into_iter().for_each(|adj| self.risk_cache.insert(adj, 1.0));
+                } else {
+                    continue;
+                }
+                changed = true;
             }
-            new_probs
-        } else {
-            num_probs
-        };
-
-        let num = if first_zero && self.risk_cache.is_empty() && num_probs[0] != 0.0 {
-            // first click always gives you a 0
-            0
-        } else {
-            WeightedIndex::new(weights).unwrap().sample(&mut rand::rng())
-        };
-
-        // finally just plug in risks
-        for (i, counts, _) in unknowns {
-            // `counts` does not take into account possible unconstrained mines yet, so fix that with the same logic as for `valid_by_num`
-            let mut final_count = 0.0;
-            for (i, x) in counts[0..=num].iter().enumerate() {
-                final_count += x * unconst_num_probs[num-i];
-            }
-
-            let weight = final_count / num_probs[num];
-            self.risk_cache.insert(world.position_of(i), weight);
         }
 
-        let unconst_weight = unconst_by_num[num] / num_probs[num];
-        for point in unconstrained {
-            self.risk_cache.insert(point, unconst_weight);
+        for (p, _) in numbers {
+            for adj in self.adjacents(p) {
+                if self.get(adj).is_some_and(|c| !c.is_revealed()) && !self.risk_cache.contains_key(adj) {
+                    let group = self.group_from(vec![adj], true);
+                    if !self.solve_group(&group) {
+                        return false;
+                    }
+                }
+            }
         }
 
-        num as u8
+        self.rebuild_regions();
+        true
     }
 
     pub fn cell_risk(&self, point: Coord) -> f32 {
         if let Some(p) = self.risk_cache.get(point) {
             // frontier
             p
-        } else if self.get(point).is_none_or(|x| x.is_revealed()) || self.risk_cache.is_empty() && self.density < 1.0 {
+        } else if self.get(point).is_none_or(|x| x.is_revealed()) || self.risk_cache.is_empty() && self.density_at(point) < 1.0 {
             // already revealed or first click
             0.0
         } else {
             // no info
-            self.density
+            self.density_at(point)
         }
     }
 
     fn reveal_cell_internal(&mut self, point: Coord, first_zero: bool) -> Option<u8> {
-        if !self.is_clear(point) {
+        if !self.decide(point).clear {
             return None;
         }
+        self.show(point, first_zero, None)
+    }
 
+    /// Reveal a cell the judge has already let through, showing `known` if it's given and a number picked by `solve_from` otherwise.
+    /// If the cell can't show `known`, the board is left as it was.
+    fn show(&mut self, point: Coord, first_zero: bool, known: Option<u8>) -> Option<u8> {
+        let risk = self.risk_cache.get(point);
         self.risk_cache.remove(point);
+        let mut affected = self.take_regions_around(point);
 
-        let num = self.solve_from(point, first_zero);
+        let Some(num) = self.solve_from(point, first_zero, known) else {
+            if let Some(risk) = risk {
+                self.risk_cache.insert(point, risk);
+            }
+            self.refresh_regions(affected);
+            return None;
+        };
         self.set(point, Cell::Revealed(num));
 
+        affected.extend(self.adjacents(point));
+        self.refresh_regions(affected);
+
         Some(num)
     }
 
@@ -321,32 +668,66 @@ impl Field {
     pub fn reveal_cell_first_zero(&mut self, point: Coord) -> Option<u8> {
         self.reveal_cell_internal(point, true)
     }
+
+    /// Reveal a cell with a number that was picked somewhere else, like by the judge on a server, working out the risks around it
+    /// as if it had been picked here. Returns false if the cell isn't hidden or couldn't have shown that number.
+    pub fn reveal_known(&mut self, point: Coord, num: u8) -> bool {
+        self.get(point).is_some_and(|cell| !cell.is_revealed()) && self.show(point, false, Some(num)).is_some()
+    }
+
+    /// Record that a hidden cell is a mine, like when the judge decided it was one for a player who gets to keep playing, and work out
+    /// the risks of the cells it was solved together with again. Returns false if the cell is known to be safe, or isn't hidden.
+    pub fn mark_mine(&mut self, point: Coord) -> bool {
+        match self.risk_cache.get(point) {
+            _ if self.get(point).is_none_or(|cell| cell.is_revealed()) => return false,
+            Some(0.0) => return false,
+            Some(1.0) => return true,
+            _ => {},
+        }
+        let others = self.group_from(vec![point], true);
+        self.risk_cache.insert(point, 1.0);
+        // without the mine, the rest of its group can fall apart into more than one
+        let mut solved = HashSet::from([point]);
+        for p in others {
+            if solved.contains(&p) {
+                continue;
+            }
+            let group = self.group_from(vec![p], true);
+            solved.extend(group.iter().copied());
+            if !group.is_empty() {
+                self.solve_group(&group);
+            }
+        }
+        self.rebuild_regions();
+        true
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use rand::prelude::*;
     use std::collections::HashMap;
+    use crate::topology::Topology;
+    use crate::bots::{Action, RandomSafe, Strategy};
     use super::*;
 
     #[test]
     fn sanity() {
-        let mut rng = rand::rng();
         let mut field = Field::default();
         let _ = field.reveal_cell((0, 0));
         for _ in 0..1000 {
-            let point = *field.safe_frontier().choose(&mut rng).unwrap();
+            let Some(Action::Reveal(point)) = RandomSafe.next_action(&field) else { panic!("nowhere safe to click") };
             let _ = field.reveal_cell(point);
         }
 
         for risk in field.risk_cache.values() {
-            assert!(risk.is_finite() && risk >= 0.0 && risk <= 1.0, "risk {:?} is not sane", risk);
+            assert!(risk.is_finite() && (0.0..=1.0).contains(&risk), "risk {:?} is not sane", risk);
         }
 
         let mut surrounding_info: HashMap<Coord, (u8, u8, u8)> = HashMap::new();
 
         for (point, risk) in field.risk_cache.iter() {
-            for neighbour in adjacents(point) {
+            for neighbour in field.adjacents(point) {
                 if field.get(neighbour).unwrap().is_revealed() {
                     let e = surrounding_info.entry(neighbour).or_default();
                     if risk == 0.0 {
@@ -367,4 +748,255 @@ mod tests {
             assert!(conf_safes <= 8 - num);
         }
     }
+
+    #[test]
+    fn parallel_enumeration() {
+        let mut rng = rand::rng();
+        let mut field = Field::default();
+
+        // a strip of numbers with unknowns on either side
+        let mines: HashMap<Coord, bool> = (-1..=12).flat_map(|x| [(x, -1), (x, 1)]).map(|p| (p, rng.random_bool(0.4))).collect();
+        for x in 0..=10 {
+            let n = adjacents((x, 0)).filter(|p| mines.get(p) == Some(&true)).count();
+            field.set((x, 0), Cell::Revealed(n as u8));
+        }
+
+        let mut world = SmallWorld::new(&field, rect((-2, -2), (16, 5)));
+        let target = world.index_of_unchecked((11, 0));
+        *world.get_mut(target) = Some((!0, 0));
+        let unknowns: Vec<_> = mines.keys().map(|&p| world.index_of_unchecked(p)).collect();
+        for &i in &unknowns {
+            world.add_unknown(i);
+        }
+
+        let serial = world.clone().enumerate(&unknowns, &mut vec![false; unknowns.len()], 0, Some(target));
+        let parallel = world.enumerate_parallel(&unknowns, Some(target), 4);
+        assert_tallies_eq(&serial, &parallel);
+    }
+
+    #[test]
+    fn component_memo() {
+        let mut field = Field::default();
+
+        // two separate strips of numbers, both touching the target cell
+        for x in 2..=7 {
+            field.set((x, 0), Cell::Revealed((x % 3) as u8));
+            field.set((-x, 0), Cell::Revealed((x % 2) as u8 + 1));
+        }
+
+        let mut world = SmallWorld::new(&field, rect((-9, -2), (19, 5)));
+        let target = world.index_of_unchecked((0, 0));
+        *world.get_mut(target) = Some((!0, 0));
+        let unknowns: Vec<_> = (1..=8).flat_map(|x| [(x, -1), (x, 1), (-x, -1), (-x, 1)]).map(|p| world.index_of_unchecked(p)).collect();
+        for &i in &unknowns {
+            world.add_unknown(i);
+        }
+
+        assert_eq!(world.components(&unknowns, Some(target)).len(), 2);
+        let whole = world.clone().enumerate(&unknowns, &mut vec![false; unknowns.len()], 0, Some(target));
+        let split = field.tally(&mut world, &unknowns, Some(target));
+        assert_tallies_eq(&whole, &split);
+        assert_eq!(field.memo.len(), 2);
+        let remembered = field.tally(&mut world, &unknowns, Some(target));
+        assert_tallies_eq(&whole, &remembered);
+        assert_eq!(field.memo.len(), 2);
+    }
+
+    #[test]
+    fn certain_mines_stay_certain() {
+        let mut field = Field::default();
+        field.density = 0.4;
+
+        // several small components on either side of the target cell, some of which force mines
+        for (x, n) in [(-5, 1), (-4, 2), (-2, 2), (3, 1), (4, 1), (5, 3), (6, 2), (7, 2), (8, 0)] {
+            field.set((x, 0), Cell::Revealed(n));
+        }
+
+        let mut world = SmallWorld::new(&field, rect((-10, -2), (21, 5)));
+        let target = world.index_of_unchecked((0, 0));
+        *world.get_mut(target) = Some((!0, 0));
+        let unknowns: Vec<_> = (1..=9).flat_map(|x| [(x, -1), (x, 1), (-x, -1), (-x, 1)]).map(|p| world.index_of_unchecked(p)).collect();
+        for &i in &unknowns {
+            world.add_unknown(i);
+        }
+
+        let whole = world.clone().enumerate(&unknowns, &mut vec![false; unknowns.len()], 0, Some(target));
+        let split = field.tally(&mut world, &unknowns, Some(target));
+        for (xs, ys) in whole.counts.iter().zip(&split.counts) {
+            for ((x, y), (v, w)) in xs.iter().zip(ys).zip(whole.valid_by_num.iter().zip(split.valid_by_num)) {
+                if x == v {
+                    assert_eq!(*y, w);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn torus() {
+        let import = |text: &str| Field::import_text(&mut text.as_bytes()).unwrap();
+
+        // every cell of a 3x3 torus is next to every other
+        let field = import("bounds 3x3\nwrap true\n\n1##\n###\n###\n");
+        assert_eq!(field.adjacents((-1, -1)).collect::<HashSet<_>>().len(), 8);
+        assert_eq!(field.risks().len(), 8);
+        assert!(field.risks().iter().all(|(_, r)| (r - 0.125).abs() < 1e-6));
+
+        // a 0 in the corner clears the other corners
+        let mut field = import("bounds 4x4\nwrap true\n\n0###\n####\n####\n####\n");
+        for corner in [(1, -2), (-2, 1), (1, 1)] {
+            assert_eq!(field.risks().get(corner), Some(0.0));
+        }
+        assert!(field.reveal_cell((1, 1)).is_some());
+        // next to (1, 1) across the right edge
+        assert!(field.risks().contains_key((-2, 0)));
+        assert_eq!(field.wrap((2, 2)), (-2, -2));
+    }
+
+    #[test]
+    fn topologies() {
+        let import = |text: &str| Field::import_text(&mut text.as_bytes()).unwrap();
+
+        let field = import("topology von-neumann\nbounds 3x3\n\n###\n#1#\n###\n");
+        assert_eq!(field.risks().len(), 4);
+        assert_eq!(field.risks().get((-1, -2)), Some(0.25));
+        assert_eq!(field.risks().get((-2, -2)), None);
+
+        let field = import("topology radius2\nbounds 5x5\n\n#####\n#####\n##o##\n#####\n#####\n");
+        assert_eq!(field.risks().len(), 24);
+        assert!(field.risks().iter().all(|(_, r)| r == 1.0));
+
+        // odd rows are shifted right, so (0, -1) is next to (1, -2) but not (-1, -2)
+        let field = import("topology hex\nbounds 4x4\n\n####\n##1#\n####\n####\n");
+        assert_eq!(field.risks().len(), 6);
+        assert!(field.risks().contains_key((1, -2)));
+        assert!(!field.risks().contains_key((-1, -2)));
+
+        let field = import("topology knight\nbounds 5x5\n\n#####\n#####\n##2##\n#####\n#####\n");
+        assert_eq!(field.risks().len(), 8);
+        assert_eq!(field.risks().get((0, -3)), Some(0.25));
+
+        // everything on the board is next to the middle, and any number up to 24 can come up
+        let mut field = Field::new(0.5, crate::Judge::Kind, false, Some((5, 5)));
+        field.set_topology(Topology::Radius2);
+        let n = field.reveal_cell((-1, -1)).unwrap();
+        assert_eq!(field.risks().len(), 24);
+        assert!(field.risks().iter().all(|(_, r)| (r - n as f32 / 24.0).abs() < 1e-6));
+
+        // neighbours further away make much bigger groups, which are too slow to play through here
+        for topology in [Topology::Moore, Topology::VonNeumann, Topology::Hex] {
+            let mut rng = rand::rng();
+            let mut field = Field::new(0.2, crate::Judge::Kind, false, None);
+            field.set_topology(topology);
+            field.reveal_cell_first_zero((0, 0)).unwrap();
+            for _ in 0..30 {
+                let Some(&point) = field.safe_frontier().choose(&mut rng) else { break };
+                let n = field.reveal_cell(point).unwrap();
+                assert!(n as usize <= topology.neighbours());
+            }
+            assert!(field.risks().iter().all(|(_, r)| (0.0..=1.0).contains(&r)), "{topology} has a risk out of range");
+        }
+    }
+
+    #[test]
+    fn varying_density() {
+        let import = |text: &str| Field::import_text(&mut text.as_bytes()).unwrap();
+
+        // the left cell can't be a mine, so the right one must be
+        let field = import("zone -9 -9 -2 9 0\nbounds 3x1\n\n#1#\n");
+        assert_eq!(field.risks().get((-2, -1)), Some(0.0));
+        assert_eq!(field.risks().get((0, -1)), Some(1.0));
+
+        // a lower density on one side makes those cells less likely to be the mine
+        let field = import("zone -9 -9 -1 9 0.1\nbounds 3x2\n\n#1#\n###\n");
+        assert!(field.risks().get((-2, -1)).unwrap() < field.risks().get((0, -1)).unwrap());
+
+        let mut rng = rand::rng();
+        let mut field = Field::new(0.2, crate::Judge::Kind, false, None);
+        field.set_density_map(crate::DensityMap::parse("ramp 0.02 0.4\nzone 3 -20 20 20 0").unwrap());
+        field.reveal_cell_first_zero((0, 0)).unwrap();
+        for _ in 0..100 {
+            let Some(&point) = field.safe_frontier().choose(&mut rng) else { break };
+            field.reveal_cell(point).unwrap();
+        }
+        for (point, risk) in field.risks().iter() {
+            assert!((0.0..=1.0).contains(&risk));
+            if (3..=20).contains(&point.0) && (-20..=20).contains(&point.1) {
+                assert_eq!(risk, 0.0, "{point:?} is in a zone without mines");
+            }
+        }
+        assert_eq!(field.cell_risk((-100, 0)), 0.4);
+    }
+
+    #[test]
+    fn seeded() {
+        // play two fields with the same seed the same way, guessing with the random judge so that it has to agree too
+        let play = |seed| {
+            let mut field = Field::new(0.3, crate::Judge::Random, false, Some((12, 12)));
+            field.set_seed(Some(seed));
+            let mut text = Vec::new();
+            if field.reveal_cell_first_zero((0, 0)).is_some() {
+                for _ in 0..40 {
+                    let Some((point, _)) = field.risks().iter().min_by_key(|&((x, y), r)| (r > 0.0, (r * 1000.0) as u32, (y, x))) else { break };
+                    if field.reveal_cell(point).is_none() {
+                        break;
+                    }
+                }
+            }
+            field.export_text(&mut text).unwrap();
+            text
+        };
+        for seed in 0..5 {
+            assert_eq!(play(seed), play(seed), "seed {seed} played out differently");
+        }
+        assert!((0..5).any(|seed| play(seed) != play(seed + 5)), "different seeds all played out the same");
+    }
+
+    #[test]
+    fn known_numbers() {
+        let mut field = Field::new(0.2, crate::Judge::Kind, false, None);
+        let mut copy = field.clone();
+        let mut point = (0, 0);
+        for _ in 0..40 {
+            let num = field.reveal_cell_first_zero(point).unwrap();
+            let before: HashMap<_, _> = copy.risks().iter().collect();
+            assert!(!copy.reveal_known(point, 9), "a cell showed a number it couldn't have");
+            assert_eq!(copy.risks().iter().collect::<HashMap<_, _>>(), before);
+            assert!(copy.reveal_known(point, num));
+            assert!(!copy.reveal_known(point, num), "a cell was revealed twice");
+            assert_eq!(copy.cells().collect::<HashMap<_, _>>(), field.cells().collect());
+            assert_eq!(copy.risks().iter().collect::<HashMap<_, _>>(), field.risks().iter().collect());
+            let Some((next, _)) = field.risks().nearest((0, 0), field.risks().global_best()) else { break };
+            point = next;
+        }
+    }
+
+    #[test]
+    fn marked_mines() {
+        let mut field = Field::import_text(&mut "bounds 4x1\n\n#1##\n".as_bytes()).unwrap();
+        let (x, y) = top_left((4, 1));
+        assert_eq!(field.risks().get((x, y)), Some(0.5));
+        assert!(field.mark_mine((x, y)));
+        assert_eq!(field.risks().get((x, y)), Some(1.0));
+        assert_eq!(field.risks().get((x + 2, y)), Some(0.0));
+        assert_eq!(field.reveal_cell((x, y)), None);
+        assert!(!field.mark_mine((x + 2, y)), "a safe cell was marked as a mine");
+        assert!(!field.mark_mine((x + 1, y)), "a number was marked as a mine");
+
+        // far from any number, nothing else changes
+        let mut field = Field::new(0.2, crate::Judge::Kind, false, None);
+        assert!(field.mark_mine((50, 50)));
+        assert_eq!(field.risks().iter().collect::<Vec<_>>(), [((50, 50), 1.0)]);
+    }
+
+    fn rect((ox, oy): Coord, (width, height): (isize, isize)) -> impl Iterator<Item=Coord> {
+        (oy..oy+height).flat_map(move |y| (ox..ox+width).map(move |x| (x, y)))
+    }
+
+    fn assert_tallies_eq(x: &Tally, y: &Tally) {
+        let close = |x: f64, y: f64| (x - y).abs() <= 1e-5 * x.abs().max(y.abs()).max(1e-30);
+        assert!(x.valid_by_num.into_iter().zip(y.valid_by_num).all(|(x, y)| close(x, y)));
+        for (xs, ys) in x.counts.iter().zip(&y.counts) {
+            assert!(xs.iter().zip(ys).all(|(&x, &y)| close(x, y)));
+        }
+    }
 }
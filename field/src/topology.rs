@@ -0,0 +1,126 @@
+use savefile::prelude::Savefile;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::field::Coord;
+
+/// Which cells count as next to each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Savefile)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Topology {
+    /// The 8 cells around a cell, as in normal minesweeper.
+    #[default]
+    Moore,
+    /// Only the 4 cells that share an edge with a cell.
+    VonNeumann,
+    /// A grid of hexagons, where each row is offset from the one before by half a cell.
+    /// Odd rows are the ones shifted to the right.
+    Hex,
+    /// The 8 cells a knight's move away.
+    Knight,
+    /// The 24 cells at most 2 steps away in each direction.
+    Radius2,
+}
+use Topology::*;
+
+const MOORE: [Coord; 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+const VON_NEUMANN: [Coord; 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+const HEX_EVEN: [Coord; 6] = [(-1, -1), (0, -1), (1, 0), (0, 1), (-1, 1), (-1, 0)];
+const HEX_ODD: [Coord; 6] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 0)];
+const KNIGHT: [Coord; 8] = [(1, -2), (2, -1), (2, 1), (1, 2), (-1, 2), (-2, 1), (-2, -1), (-1, -2)];
+const RADIUS_2: [Coord; 24] = [
+    (-2, -2), (-1, -2), (0, -2), (1, -2), (2, -2),
+    (-2, -1), (-1, -1), (0, -1), (1, -1), (2, -1),
+    (-2, 0), (-1, 0), (1, 0), (2, 0),
+    (-2, 1), (-1, 1), (0, 1), (1, 1), (2, 1),
+    (-2, 2), (-1, 2), (0, 2), (1, 2), (2, 2),
+];
+
+impl Topology {
+    pub const ALL: [Topology; 5] = [Moore, VonNeumann, Hex, Knight, Radius2];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Moore => "moore",
+            VonNeumann => "von-neumann",
+            Hex => "hex",
+            Knight => "knight",
+            Radius2 => "radius2",
+        }
+    }
+
+    /// How many neighbours every cell has, which is also the highest number a cell can show.
+    pub fn neighbours(self) -> usize {
+        match self {
+            Moore | Knight => 8,
+            VonNeumann => 4,
+            Hex => 6,
+            Radius2 => 24,
+        }
+    }
+
+    /// How far away neighbours can be in each direction.
+    pub(crate) fn reach(self) -> usize {
+        match self {
+            Moore | VonNeumann | Hex => 1,
+            Knight | Radius2 => 2,
+        }
+    }
+
+    /// Whether a board of the given size can wrap around without any cell being next to another one twice.
+    pub fn can_wrap(self, (width, height): (usize, usize)) -> bool {
+        let least = 2*self.reach() + 1;
+        // rows only line up with the rows on the other side if there are an even number of them
+        width >= least && height >= least && (self != Hex || height % 2 == 0)
+    }
+
+    fn offsets(self, y: isize) -> &'static [Coord] {
+        match self {
+            Moore => &MOORE,
+            VonNeumann => &VON_NEUMANN,
+            Hex if y.rem_euclid(2) == 0 => &HEX_EVEN,
+            Hex => &HEX_ODD,
+            Knight => &KNIGHT,
+            Radius2 => &RADIUS_2,
+        }
+    }
+
+    /// The cells next to a point, ignoring the edges of the board.
+    pub fn adjacents(self, (x, y): Coord) -> impl Iterator<Item=Coord> + 'static {
+        self.offsets(y).iter().map(move |&(dx, dy)| (x + dx, y + dy))
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Topology {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|t| t.name() == s).ok_or("unknown topology")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symmetric() {
+        for topology in Topology::ALL {
+            for y in [0, 1] {
+                let point = (3, y);
+                assert_eq!(topology.adjacents(point).count(), topology.neighbours());
+                for adj in topology.adjacents(point) {
+                    assert_ne!(adj, point);
+                    assert!(topology.adjacents(adj).any(|p| p == point), "{topology}: {adj:?} isn't next to {point:?}");
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use rand::prelude::*;
-use minefair_field::{Field, Judge, Cell, adjacents};
+use minefair_field::{Action, Field, Judge, Cell, RandomSafe, Strategy};
 use std::fs::{File, OpenOptions};
-use std::io::{Write, Read, Seek, Result, SeekFrom, BufReader, BufWriter, ErrorKind};
+use std::io::{Write, Read, Seek, Result, BufReader, BufWriter, ErrorKind};
 use std::time::{Duration, SystemTime};
 use std::collections::{HashMap, HashSet};
 
@@ -14,8 +14,8 @@ const MIN_WINNER_DIFF: f32 = 0.05;
 const MIN_INSANE_FRONTIER: usize = 9;
 const MAX_INSANE_FRONTIER: usize = 20;
 
-fn click(rng: &mut impl Rng, field: &mut Field) -> bool {
-    let Some(&point) = field.safe_frontier().choose(rng) else { return false };
+fn click(field: &mut Field) -> bool {
+    let Some(Action::Reveal(point)) = RandomSafe.next_action(field) else { return false };
     let _ = field.reveal_cell(point);
     true
 }
@@ -29,16 +29,16 @@ fn gen_puzzle(insane: bool) -> Field {
         let _ = field.reveal_cell((0, 0));
 
         for _ in 0..MIN_CLICKS {
-            if !click(&mut rng, &mut field) {
+            if !click(&mut field) {
                 continue 'retry;
             }
         }
 
-        field.judge = Judge::Strict;
+        field.set_rules(Judge::Strict, false, field.tolerance());
 
         for _ in MIN_CLICKS..MAX_CLICKS {
             if field.risks().global_best() > 0.0 { break }
-            click(&mut rng, &mut field);
+            click(&mut field);
         }
 
         if insane {
@@ -60,7 +60,7 @@ fn gen_puzzle(insane: bool) -> Field {
         let mut by_clue = HashMap::<Vec<(isize, isize)>, bool>::new();
         for (pos, risk) in field.risks().iter() {
             if risk == 1.0 { continue }
-            let mut clue: Vec<_> = adjacents(pos).filter(|&adj| field.get(adj).is_some_and(|x| x.is_revealed())).collect();
+            let mut clue: Vec<_> = field.adjacents(pos).filter(|&adj| field.get(adj).is_some_and(|x| x.is_revealed())).collect();
             clue.sort();
             by_clue.entry(clue).and_modify(|v| *v = false).or_insert(true);
         }
@@ -131,7 +131,7 @@ fn main() -> Result<()> {
     }
 
     let mut start = today(insane) + 1;
-    let file = OpenOptions::new().read(true).write(true).create(true).open(target.as_deref().unwrap_or("puzzles"))?;
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(target.as_deref().unwrap_or("puzzles"))?;
 
     let mut reader = BufReader::new(file);
     for i in 0..start {
@@ -150,7 +150,7 @@ fn main() -> Result<()> {
         let height = f32::from_le_bytes(buf);
         reader.seek_relative(4 * ((width as i64 * height as i64) + 1))?;
     }
-    let pos = reader.seek(SeekFrom::Current(0))?;
+    let pos = reader.stream_position()?;
 
     let file = reader.into_inner();
     file.set_len(pos)?;
@@ -0,0 +1,191 @@
+//! Plays lots of games without a terminal, to compare judges and ways of playing by numbers instead of by feel.
+//! Every combination of the given strategies, judges, densities and solvable settings is played, and the results are written as CSV.
+
+#![forbid(unsafe_code)]
+
+use clap::Parser;
+use minefair_field::{Action, Bot, Field, Judge, parse_density, parse_size};
+use std::io::{self, stdout, Write};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Parser)]
+#[clap(author, version, about = "Play minefair games headlessly and report how they went as CSV.", long_about = None)]
+struct Args {
+    #[clap(long, short, default_value = "1000", help = "How many games to play for each combination of settings.")]
+    games: NonZeroUsize,
+    #[clap(long, short, value_enum, value_delimiter = ',', default_value = "random,lowest-risk,closest")]
+    strategies: Vec<Bot>,
+    #[clap(long, short, value_enum, value_delimiter = ',', default_value = "local")]
+    judges: Vec<Judge>,
+    #[clap(long, short, value_delimiter = ',', default_value = "0.22", value_parser = parse_density)]
+    densities: Vec<f32>,
+    #[clap(long, value_delimiter = ',', default_value = "false", help = "Whether to keep the board solvable. Pass true,false to try both.")]
+    solvable: Vec<bool>,
+    #[clap(long, short, help = "Dimensions for finite boards, which can be won. Games on infinite boards stop after --max-clicks.", value_parser = parse_size)]
+    bounds: Option<(usize, usize)>,
+    #[clap(long, short, default_value = "500", help = "Stop a game after this many tiles have been clicked.")]
+    max_clicks: usize,
+    #[clap(long, short, help = "How many games to play at once. Defaults to the available parallelism.")]
+    threads: Option<NonZeroUsize>,
+    #[clap(long, help = "Write a row for every game instead of a summary of each combination.")]
+    per_game: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Outcome {
+    Died,
+    Won,
+    /// The click limit was reached.
+    Survived,
+    /// The strategy couldn't find anything to click.
+    Stuck,
+}
+
+impl Outcome {
+    fn name(self) -> &'static str {
+        match self {
+            Outcome::Died => "died",
+            Outcome::Won => "won",
+            Outcome::Survived => "survived",
+            Outcome::Stuck => "stuck",
+        }
+    }
+}
+
+struct Game {
+    outcome: Outcome,
+    clicks: usize,
+    cells_revealed: usize,
+    /// How long each click took to judge and solve.
+    solve_times: Vec<Duration>,
+}
+
+#[derive(Clone, Copy)]
+struct Settings {
+    strategy: Bot,
+    judge: Judge,
+    density: f32,
+    solvable: bool,
+}
+
+impl Settings {
+    fn csv(&self) -> String {
+        format!("{},{},{},{}", self.strategy, self.judge, self.density, self.solvable)
+    }
+}
+
+fn play(settings: Settings, args: &Args) -> Game {
+    let mut field = Field::new(settings.density, settings.judge, settings.solvable, args.bounds);
+    // the games are already spread over the threads
+    field.set_threads(NonZeroUsize::new(1));
+    let mut strategy = settings.strategy.strategy();
+    let mut solve_times = Vec::new();
+    let mut clicks = 0;
+    let mut next = Some(Action::Reveal((0, 0)));
+    let outcome = loop {
+        let point = match next {
+            Some(Action::Reveal(point)) => point,
+            Some(Action::Flag(point)) => {
+                field.toggle_flag(point);
+                next = strategy.next_action(&field);
+                continue;
+            },
+            None => break Outcome::Stuck,
+        };
+        let start = Instant::now();
+        let revealed = if clicks == 0 { field.reveal_cell_first_zero(point) } else { field.reveal_cell(point) };
+        solve_times.push(start.elapsed());
+        clicks += 1;
+        if revealed.is_none() {
+            break Outcome::Died;
+        } else if field.is_won() {
+            break Outcome::Won;
+        } else if clicks >= args.max_clicks {
+            break Outcome::Survived;
+        }
+        next = strategy.next_action(&field);
+    };
+    Game { outcome, clicks, cells_revealed: field.cells_revealed(), solve_times }
+}
+
+/// The value below which `p` of the sorted values fall, by the nearest rank.
+fn percentile<T: Copy + Default>(sorted: &[T], p: f64) -> T {
+    if sorted.is_empty() {
+        return T::default();
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn summarise(settings: Settings, games: &[Game]) -> String {
+    let count = |outcome| games.iter().filter(|g| g.outcome == outcome).count();
+    let mut revealed: Vec<usize> = games.iter().map(|g| g.cells_revealed).collect();
+    revealed.sort();
+    let mut times: Vec<Duration> = games.iter().flat_map(|g| g.solve_times.iter().copied()).collect();
+    times.sort();
+    let micros = |p| percentile(&times, p).as_micros();
+    format!(
+        "{},{},{},{},{},{},{:.4},{},{},{},{},{},{}",
+        settings.csv(), games.len(), count(Outcome::Died), count(Outcome::Won), count(Outcome::Survived), count(Outcome::Stuck),
+        1.0 - count(Outcome::Died) as f64 / games.len() as f64,
+        percentile(&revealed, 0.1), percentile(&revealed, 0.5), percentile(&revealed, 0.9),
+        micros(0.5), micros(0.9), micros(0.99),
+    )
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let games = args.games.get();
+    let threads = args.threads.or_else(|| std::thread::available_parallelism().ok()).map_or(1, NonZeroUsize::get);
+
+    let mut combinations = Vec::new();
+    for &strategy in &args.strategies {
+        for &judge in &args.judges {
+            for &density in &args.densities {
+                for &solvable in &args.solvable {
+                    combinations.push(Settings { strategy, judge, density, solvable });
+                }
+            }
+        }
+    }
+
+    let mut out = stdout().lock();
+    if args.per_game {
+        writeln!(out, "strategy,judge,density,solvable,game,outcome,clicks,cells_revealed,solve_us")?;
+    } else {
+        writeln!(
+            out,
+            "strategy,judge,density,solvable,games,died,won,survived,stuck,survival_rate,revealed_p10,revealed_p50,revealed_p90,solve_us_p50,solve_us_p90,solve_us_p99",
+        )?;
+    }
+
+    for settings in combinations {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(games));
+        std::thread::scope(|s| {
+            for _ in 0..threads.min(games) {
+                s.spawn(|| {
+                    while next.fetch_add(1, Ordering::Relaxed) < games {
+                        let game = play(settings, &args);
+                        results.lock().unwrap().push(game);
+                    }
+                });
+            }
+        });
+        let results = results.into_inner().unwrap();
+
+        if args.per_game {
+            for (i, game) in results.iter().enumerate() {
+                let solve: Duration = game.solve_times.iter().sum();
+                writeln!(out, "{},{i},{},{},{},{}", settings.csv(), game.outcome.name(), game.clicks, game.cells_revealed, solve.as_micros())?;
+            }
+        } else {
+            writeln!(out, "{}", summarise(settings, &results))?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
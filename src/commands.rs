@@ -0,0 +1,154 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use minefair_field::Field;
+
+use crate::saves::SaveSlot;
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Write the board in the save file out as text.
+    Export {
+        #[clap(help = "Where to write the board. Defaults to stdout.")]
+        output: Option<PathBuf>,
+        #[clap(long, help = "Write the whole game state as JSON instead, including the risk of each tile.")]
+        json: bool,
+    },
+    /// Print what's in the save file and check it for problems.
+    Inspect {
+        #[clap(long, help = "Fix any problems found by counting revealed tiles again and working out every risk from scratch.")]
+        repair: bool,
+    },
+    /// Replace the save file with a board written as text.
+    Import {
+        #[clap(help = "The board to read. Use - for stdin.")]
+        input: PathBuf,
+        #[clap(long, short, help = "Overwrite the save file if it already exists.")]
+        force: bool,
+    },
+    /// Watch the game in the save file while it's played somewhere else, without being able to change it.
+    /// The game has to be saved as it's played, so the player needs --autosave.
+    Watch,
+    /// Let other people play the game in the save file with you over the network, with `minefair connect`.
+    Serve {
+        #[clap(default_value = "0.0.0.0:7777", help = "The address to listen on.")]
+        address: String,
+        #[clap(long, help = "Race instead: everyone gets their own copy of a new board, made from the flags, and the save file isn't used.")]
+        versus: bool,
+    },
+    /// Play on a board shared by `minefair serve`. The save file isn't touched; the server keeps the game.
+    Connect {
+        #[clap(default_value = "127.0.0.1:7777", help = "The address of the server.")]
+        address: String,
+        #[clap(long, short, env = "USER", default_value = "", help = "The name to show the other players.")]
+        name: String,
+    },
+}
+
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
+
+fn write_board(field: &Field, writer: &mut impl Write, json: bool) -> io::Result<()> {
+    if json {
+        serde_json::to_writer(&mut *writer, field)?;
+        writeln!(writer)
+    } else {
+        field.export_text(writer)
+    }
+}
+
+fn export(slot: &SaveSlot, output: Option<PathBuf>, json: bool) {
+    let field = slot.load().unwrap_or_else(|e| fail(format_args!("couldn't load save file: {e}")));
+    let result = match output {
+        Some(path) => {
+            let file = File::create(path).unwrap_or_else(|e| fail(format_args!("couldn't create output file: {e}")));
+            let mut writer = BufWriter::new(file);
+            write_board(&field, &mut writer, json).and_then(|()| writer.flush())
+        },
+        None => write_board(&field, &mut io::stdout().lock(), json),
+    };
+    result.unwrap_or_else(|e| fail(format_args!("couldn't write board: {e}")));
+}
+
+fn inspect(slot: &SaveSlot, repair: bool) {
+    let mut field = slot.load().unwrap_or_else(|e| fail(format_args!("couldn't load save file: {e}")));
+    let report = field.inspect();
+
+    let bounds = match (field.size(), field.area()) {
+        (Some((w, h)), Some(area)) if area < w*h => format!("{w}x{h}, shaped with {area} tiles"),
+        (Some((w, h)), _) => format!("{w}x{h}"),
+        _ => "none".to_owned(),
+    };
+    println!(
+        "density {}, judge {}, tolerance {}, solvable {}, bias {}, topology {}, bounds {bounds}, wrap {}",
+        field.density(), field.judge(), field.tolerance(), field.solvable(), field.bias(), field.topology(), field.wraps(),
+    );
+    if let Some(seed) = field.seed() {
+        println!("seeded with {seed}");
+    }
+    if !field.density_map().is_uniform() {
+        print!("density map:\n{}", field.density_map());
+    }
+    println!("played for {}s", field.time_elapsed().as_secs());
+    if field.mixed_rules() {
+        println!("the rules have changed during the game. before now, they were:");
+    }
+    for change in field.rule_history() {
+        let tolerance = if change.judge.has_tolerance() { format!(" (tolerance {})", change.tolerance) } else { String::new() };
+        println!(
+            "  judge {}{tolerance}, solvable {} until {}s in, with {} tiles revealed",
+            change.judge, change.solvable, change.time_elapsed.as_secs(), change.cells_revealed,
+        );
+    }
+    println!("{} tiles revealed ({} counted)", field.cells_revealed(), report.counted_revealed);
+    println!("{} risks cached", field.risks().len());
+    println!("chunks stored: {}", report.chunks.len());
+    for chunk in &report.chunks {
+        println!("  {:?}: {} revealed, {} flagged", chunk.position, chunk.revealed, chunk.flagged);
+    }
+
+    if report.problems.is_empty() {
+        println!("no problems found");
+    } else {
+        println!("problems found: {}", report.problems.len());
+        for problem in &report.problems {
+            println!("  {problem}");
+        }
+    }
+
+    if repair {
+        if !field.repair() {
+            fail("can't repair the save, because the numbers on the board contradict each other");
+        }
+        slot.write(&field).unwrap_or_else(|e| fail(format_args!("couldn't write save file: {e}")));
+        println!("repaired. the old save was kept as a backup");
+    }
+}
+
+fn import(slot: &SaveSlot, input: PathBuf, force: bool) {
+    if slot.exists() && !force {
+        fail("save file already exists. pass --force to overwrite it");
+    }
+    let field = if input.as_os_str() == "-" {
+        Field::import_text(&mut io::stdin().lock())
+    } else {
+        let file = File::open(input).unwrap_or_else(|e| fail(format_args!("couldn't open board: {e}")));
+        Field::import_text(&mut BufReader::new(file))
+    };
+    let field = field.unwrap_or_else(|e| fail(format_args!("couldn't import board: {e}")));
+    slot.write(&field).unwrap_or_else(|e| fail(format_args!("couldn't write save file: {e}")));
+}
+
+pub fn run(command: Command, save_path: PathBuf) {
+    let slot = SaveSlot::new(save_path);
+    match command {
+        Command::Export { output, json } => export(&slot, output, json),
+        Command::Inspect { repair } => inspect(&slot, repair),
+        Command::Import { input, force } => import(&slot, input, force),
+        Command::Watch | Command::Serve { .. } | Command::Connect { .. } => unreachable!("games are played from main"),
+    }
+}
@@ -0,0 +1,215 @@
+//! The game itself, apart from how it's shown: what clicks do, keeping time and saving.
+//! Shared by the terminal UI, the line protocol and the multiplayer server.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::Instant;
+
+use minefair_field::{Cell, Field};
+use crate::saves::{SaveSlot, confirm};
+use crate::Args;
+
+pub type Coord = (isize, isize);
+
+/// The furthest from the middle of the board a tile can be asked about, so that finding its neighbours can't overflow.
+pub const MAX_COORD: isize = isize::MAX / 4;
+
+/// Whether a tile is close enough to the middle of the board to be asked about. See `MAX_COORD`.
+pub fn in_range((x, y): Coord) -> bool {
+    let range = -MAX_COORD..=MAX_COORD;
+    range.contains(&x) && range.contains(&y)
+}
+
+/// The most tiles one click can reveal, so that clicking into a huge empty area doesn't hang the game.
+const MAX_REVEALED: usize = 2401;
+
+pub struct Game {
+    pub field: Field,
+    pub dead: bool,
+    /// Where the game is saved. Clients of a multiplayer server leave saving to the server.
+    save_slot: Option<SaveSlot>,
+    last_time_pass: Option<Instant>,
+}
+
+/// What happened when a tile was clicked.
+pub struct Reveal {
+    /// Every tile that was revealed, in order, with its number.
+    pub revealed: Vec<(Coord, u8)>,
+    /// The tile that turned out to be a mine, if one did.
+    pub death: Option<Coord>,
+}
+
+impl Game {
+    /// A new game with the settings in the arguments.
+    fn new(args: &Args, save_slot: SaveSlot) -> Self {
+        Self { field: new_field(args), dead: false, save_slot: Some(save_slot), last_time_pass: None }
+    }
+
+    /// A game that isn't saved here, because it belongs to a server or only lasts for one round of versus.
+    pub fn remote(field: Field) -> Self {
+        Self { field, dead: false, save_slot: None, last_time_pass: None }
+    }
+
+    /// Continue the game in the save slot, or start a new one if there isn't one or `--reset` was passed.
+    /// If the save is broken, `interactive` decides whether to offer to load a backup or just exit.
+    pub fn open(args: &Args, save_slot: SaveSlot, interactive: bool) -> Self {
+        if !save_slot.exists() || args.reset {
+            let mut game = Self::new(args, save_slot);
+            game.save();
+            return game;
+        }
+        let mut field = load_or_recover(&save_slot, interactive);
+        if args.apply_settings {
+            field.set_rules(args.judge, args.solvable, args.tolerance);
+        }
+        field.set_auditing(true);
+        let mut game = Self { field, dead: false, save_slot: Some(save_slot), last_time_pass: None };
+        game.init_time();
+        game
+    }
+
+    /// Start again with the same settings.
+    pub fn reset(&mut self) {
+        self.field.clear();
+        self.dead = false;
+        self.last_time_pass = None;
+    }
+
+    /// Click a tile, and die if it was a mine.
+    pub fn reveal(&mut self, clicked: Coord) -> Reveal {
+        if self.dead {
+            return Reveal { revealed: Vec::new(), death: None };
+        }
+        let result = self.spread(clicked);
+        if result.death.is_some() {
+            self.dead = true;
+            self.pass_time();
+        }
+        result
+    }
+
+    /// Click a tile without ending the game if it was a mine, for games with more than one player.
+    /// Clicking a number with as many flags around it as its number reveals everything else around it,
+    /// and revealing a zero reveals everything around that.
+    pub fn spread(&mut self, clicked: Coord) -> Reveal {
+        let mut result = Reveal { revealed: Vec::new(), death: None };
+        let mut queue = VecDeque::new();
+        let chord = matches!(self.field.get(clicked), Some(Cell::Revealed(n)) if n as usize == self.field.adjacents(clicked).filter(|&x| self.field.get(x) == Some(Cell::Hidden(true))).count());
+        if chord {
+            queue.extend(self.field.adjacents(clicked).filter(|&x| self.field.get(x) == Some(Cell::Hidden(false))));
+        } else if self.field.get(clicked) == Some(Cell::Hidden(false)) {
+            // only the player's own click is logged, not the cells it opens up
+            self.field.audit(clicked);
+            queue.push_back(clicked);
+        }
+        while !queue.is_empty() && result.revealed.len() < MAX_REVEALED {
+            let pos = queue.pop_front().unwrap();
+            let Some(Cell::Hidden(false)) = self.field.get(pos) else { continue };
+            match self.field.reveal_cell_first_zero(pos) {
+                Some(n) => {
+                    if n == 0 {
+                        queue.extend(self.field.adjacents(pos));
+                    }
+                    result.revealed.push((pos, n));
+                },
+                None => {
+                    // a chord is logged by the cell that killed the player, as that's the decision they'll want to see
+                    if chord {
+                        self.field.audit(pos);
+                    }
+                    result.death = Some(pos);
+                    return result;
+                },
+            }
+        }
+        self.init_time();
+        if !result.revealed.is_empty() && self.field.is_won() {
+            self.pass_time();
+        }
+        result
+    }
+
+    /// Flag or unflag a tile. Returns whether anything changed.
+    pub fn flag(&mut self, point: Coord) -> bool {
+        if self.dead || !matches!(self.field.get(point), Some(Cell::Hidden(_))) {
+            return false;
+        }
+        self.field.toggle_flag(point);
+        true
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.dead || self.field.is_won()
+    }
+
+    fn init_time(&mut self) {
+        if !self.field.risks().is_empty() && self.last_time_pass.is_none() {
+            self.last_time_pass = Some(Instant::now());
+        }
+    }
+
+    /// Add the time since this was last called to the field's play time.
+    pub fn pass_time(&mut self) {
+        if let Some(ltp) = self.last_time_pass {
+            self.field.pass_time(ltp.elapsed());
+            self.last_time_pass = Some(Instant::now());
+        }
+    }
+
+    pub fn save(&mut self) {
+        if !self.is_over() {
+            self.pass_time();
+        }
+        if let Some(slot) = &self.save_slot {
+            slot.write(&self.field).expect("failed to write to save file");
+        }
+    }
+
+    /// Write the judge's decisions to a file, one per line.
+    pub fn write_audit_log(&self, path: &Path) {
+        let log: String = self.field.decisions().map(|d| format!("{d}\n")).collect();
+        if let Err(e) = std::fs::write(path, log) {
+            eprintln!("couldn't write the judge's decisions to {}: {e}", path.display());
+        }
+    }
+}
+
+/// An empty board with the settings in the arguments.
+pub fn new_field(args: &Args) -> Field {
+    let mut field = Field::new(args.density, args.judge, args.solvable, args.bounds);
+    field.set_rules(args.judge, args.solvable, args.tolerance);
+    field.set_topology(args.topology);
+    field.set_density_map(args.density_map.clone().unwrap_or_default());
+    field.set_bias(args.bias);
+    field.set_shape(args.shape.clone());
+    field.set_wrapping(args.wrap);
+    field.set_seed(args.seed);
+    field.set_auditing(true);
+    field
+}
+
+/// Load the game in the save slot, offering to fall back to a backup if it's broken and we can ask.
+/// Exits if there's nothing to play.
+fn load_or_recover(slot: &SaveSlot, interactive: bool) -> Field {
+    let error = match slot.load() {
+        Ok(field) => return field,
+        Err(e) => e,
+    };
+    eprintln!("couldn't load save file: {error}");
+    if !interactive {
+        eprintln!("pass --reset to start a new game instead");
+        std::process::exit(1);
+    }
+    let Some((backup_path, field)) = slot.latest_backup() else {
+        eprintln!("there are no backups to recover from. pass --reset to start a new game instead");
+        std::process::exit(1);
+    };
+    if !confirm(&format!("load the backup at {} instead?", backup_path.display())) {
+        eprintln!("pass --reset to start a new game instead");
+        std::process::exit(1);
+    }
+    let aside = slot.set_aside().expect("failed to move the damaged save");
+    eprintln!("the damaged save was moved to {}", aside.display());
+    slot.write(&field).expect("failed to write to save file");
+    field
+}
@@ -1,22 +1,37 @@
 #![forbid(unsafe_code)]
 
+mod commands;
+mod game;
+mod net;
 mod options;
+mod protocol;
+mod saves;
 mod ui;
 
 use clap::Parser;
 use directories::ProjectDirs;
+use minefair_field::{DensityMap, Shape, parse_density, parse_size};
 
-fn parse_density(s: &str) -> Result<f32, &'static str> {
-    let f = s.parse().map_err(|_| "invalid number")?;
-    if f < 0.0 || f > 1.0 {
-        return Err("density out of range");
+fn parse_shape(s: &str) -> Result<Shape, String> {
+    match s.split_once('=') {
+        None if s == "rectangle" => Ok(Shape::Rectangle),
+        None if s == "ellipse" => Ok(Shape::Ellipse),
+        Some(("ring", thickness)) => match thickness.parse() {
+            Ok(0) => Err("the ring has to be at least 1 tile thick".to_owned()),
+            Ok(thickness) => Ok(Shape::Ring(thickness)),
+            Err(_) => Err("invalid number".to_owned()),
+        },
+        Some(("mask", path)) => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read mask: {e}"))?;
+            Shape::parse_mask(&text)
+        },
+        _ => Err("expected rectangle, ellipse, ring=THICKNESS or mask=PATH".to_owned()),
     }
-    Ok(f)
 }
 
-fn parse_size(s: &str) -> Result<(usize, usize), &'static str> {
-    let (width_part, height_part) = s.split_once('x').ok_or("bounds should be delimited with 'x'")?;
-    width_part.parse().ok().zip(height_part.parse().ok()).ok_or("invalid number")
+fn parse_density_map(path: &str) -> Result<DensityMap, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read density map: {e}"))?;
+    DensityMap::parse(&text)
 }
 
 #[derive(Parser)]
@@ -24,31 +39,67 @@ fn parse_size(s: &str) -> Result<(usize, usize), &'static str> {
 pub struct Args {
     #[clap(long, short, default_value = "0.22", help = "The density of mines, between 0 and 1.", value_parser = parse_density)]
     density: f32,
+    #[clap(
+        long, value_parser = parse_density_map,
+        help = "Read a file describing how the density changes across the board, with lines like 'ramp RATE MAX' and 'zone X1 Y1 X2 Y2 DENSITY'.",
+    )]
+    density_map: Option<DensityMap>,
     #[clap(long, short, help = "Dimensions for a finite board.", value_parser = parse_size)]
     bounds: Option<(usize, usize)>,
+    #[clap(
+        long, default_value = "rectangle", value_parser = parse_shape,
+        help = "The shape of a finite board: rectangle, ellipse, ring=THICKNESS, or mask=PATH to read one from a file. Masks set the bounds themselves.",
+    )]
+    shape: Shape,
+    #[clap(long, default_value = "moore", value_enum, help = "Which tiles count as next to each other.")]
+    topology: minefair_field::Topology,
+    #[clap(long, help = "Make the edges of a finite board wrap around to the other side, so it has no edges or corners.")]
+    wrap: bool,
     #[clap(long, short, help = "Try to keep the board solvable without guessing. Doesn't always work and often has boring effects.")]
     solvable: bool,
+    #[clap(
+        long, default_value = "none",
+        help = "Make revealed tiles prefer numbers that help: generous=STRENGTH for more safe tiles, decisive=STRENGTH for fewer undecided ones. Unlike --solvable, guesses are still needed.",
+    )]
+    bias: minefair_field::Bias,
     #[clap(long, short, default_value = "local", value_enum)]
     judge: minefair_field::Judge,
+    #[clap(
+        long, default_value = "0.05",
+        help = "How much riskier than the best tile the threshold judges let you click: a probability like 0.05, or a percentage of the best risk like 20%.",
+    )]
+    tolerance: minefair_field::Tolerance,
+    #[clap(long, help = "Make the board the same every time it's played the same way, by basing every random choice on this number.")]
+    seed: Option<u64>,
     #[clap(long, short, default_value = "frappe", value_enum)]
     theme: options::ThemeChoice,
     #[clap(long, short, default_value = "ascii", value_enum)]
     iconset: options::IconSetChoice,
+    #[clap(long, default_value = "lowest-risk", value_enum, help = "The bot that plays when autoplay is turned on with P.")]
+    bot: minefair_field::Bot,
     #[clap(long, short, help = "See what the solver sees.")]
     cheat: bool,
     #[clap(long, short, help = "Save automatically after every click.")]
     autosave: bool,
-    #[clap(long, help = "Delete and recreate the save file.")]
+    #[clap(long, help = "Apply --judge, --tolerance and --solvable to the game in an existing save file instead of ignoring them. The change is recorded in the save.")]
+    apply_settings: bool,
+    #[clap(long, help = "When the game closes, write the judge's decisions about the last 1000 clicks this session to a file, with the risk each was based on.")]
+    audit_log: Option<std::path::PathBuf>,
+    #[clap(long, help = "Play with commands from stdin instead of in the terminal, for bots. See the README for the protocol.")]
+    protocol: bool,
+    #[clap(long, help = "Start a new game, replacing the one in the save file. The old game is kept as a backup.")]
     reset: bool,
     #[clap(
         help = "The path to the save file. Will be created if it doesn't exist. Defaults to the value of MINEFAIR_SAVE if set, or to a reasonable platform-dependent config folder.",
         env = "MINEFAIR_SAVE",
     )]
     save_path: Option<std::path::PathBuf>,
+    #[clap(subcommand)]
+    command: Option<commands::Command>,
 }
 
 fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
 
     let path = args.save_path.clone().unwrap_or(
         match ProjectDirs::from("", "", "minefair") {
@@ -63,7 +114,31 @@ fn main() {
         eprintln!("is a directory");
         std::process::exit(1);
     }
+    if args.bounds.is_none() && matches!(args.shape, Shape::Ellipse | Shape::Ring(_)) {
+        eprintln!("--shape needs --bounds unless it's a mask");
+        std::process::exit(1);
+    }
+    if args.wrap && args.shape.size().or(args.bounds).is_none_or(|size| !args.topology.can_wrap(size)) {
+        eprintln!("--wrap needs a board at least 3x3 in size, or 5x5 for the knight and radius2 topologies. hex boards also need an even height");
+        std::process::exit(1);
+    }
     std::fs::create_dir_all(path.parent().unwrap()).expect("failed creating directories");
 
-    ui::game_loop(args, path).unwrap();
+    match args.command.take() {
+        Some(commands::Command::Serve { address, versus }) => net::serve(args, path, &address, versus).unwrap_or_else(|e| {
+            eprintln!("couldn't start the server: {e}");
+            std::process::exit(1);
+        }),
+        Some(commands::Command::Connect { address, name }) => ui::connect(args, &address, &name).unwrap_or_else(|e| {
+            eprintln!("couldn't play on the server: {e}");
+            std::process::exit(1);
+        }),
+        Some(commands::Command::Watch) => ui::watch(args, path).unwrap(),
+        Some(command) => commands::run(command, path),
+        None if args.protocol => protocol::run(args, path).unwrap_or_else(|e| {
+            eprintln!("stopped talking to the bot: {e}");
+            std::process::exit(1);
+        }),
+        None => ui::game_loop(args, path).unwrap(),
+    }
 }
@@ -0,0 +1,721 @@
+//! Playing one board with other people over the network.
+//!
+//! The server owns the game and the save file, and clients send it what their players do. Messages are lines of text in both directions.
+//! A client starts by sending `hello NAME`, and the server answers with `welcome ID` and the whole board. After that, it only sends what
+//! changes, like `revealed X Y N` for every tile that's revealed, which the client works out the risks from itself. Everyone shares the
+//! board, but each player dies on their own and has their own score: the number of tiles they've revealed.
+//!
+//! In versus, everyone gets their own copy of a seeded board instead, so the same clicks turn out the same way for everybody. A round is won by
+//! the first player to clear a finite board, or once everyone is dead, by whoever revealed the most.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use minefair_field::{Cell, Field};
+use crate::game::{self, Coord, Game};
+use crate::saves::SaveSlot;
+use crate::Args;
+
+/// The longest a name can be.
+const MAX_NAME: usize = 16;
+/// How long the server waits on a client that isn't reading what it's sent before giving up on it.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a client has to say hello after connecting.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a player can go without sending anything, including moving their mouse, before they're disconnected.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// The longest line a client can send. Nothing it has to say comes close.
+const MAX_LINE: u64 = 256;
+/// The most clients that can be connected at once, as each one has a thread reading from it.
+const MAX_CLIENTS: usize = 64;
+/// How often a shared game is saved while it's being played.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// What a client can tell the server.
+pub enum ToServer {
+    Hello(String),
+    Reveal(Coord),
+    Flag(Coord),
+    /// The tile the player's mouse is over.
+    Cursor(Coord),
+    /// Come back to life after dying, or start a new board after it's been won.
+    Respawn,
+    /// Send the whole board again, because a change to it didn't make sense.
+    Resync,
+}
+
+/// What the server can tell a client.
+pub enum ToClient {
+    /// The id the server gave the client.
+    Welcome(u32),
+    /// The whole board, as a save file.
+    Board(Vec<u8>),
+    /// A tile was revealed with a number on the board.
+    Revealed(Coord, u8),
+    /// A tile was flagged or unflagged.
+    Flagged(Coord, bool),
+    /// Someone died on a tile, so it's known to be a mine from now on.
+    Mine(Coord),
+    Player { id: u32, score: usize, alive: bool, name: String },
+    Left(u32),
+    Cursor(u32, Coord),
+    /// A round of versus has started, with everyone back to an empty board.
+    Round(u64),
+    /// A player cleared their board in versus, taking this long.
+    Cleared(u32, Duration),
+    /// A player won the round of versus.
+    Winner(u32),
+    /// There's no room for another player. Sent instead of a welcome.
+    Full,
+}
+
+fn parse_words<T: FromStr, const N: usize>(words: &[&str]) -> Option<[T; N]> {
+    let parsed: Vec<T> = words.iter().map(|w| w.parse().ok()).collect::<Option<_>>()?;
+    parsed.try_into().ok()
+}
+
+fn parse_point(words: &[&str]) -> Option<Coord> {
+    parse_words(words).map(|[x, y]| (x, y)).filter(|&point| game::in_range(point))
+}
+
+fn clean_name(name: &str) -> String {
+    name.chars().filter(|c| !c.is_control()).take(MAX_NAME).collect::<String>().trim().to_owned()
+}
+
+impl fmt::Display for ToServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ToServer::Hello(name) => write!(f, "hello {name}"),
+            ToServer::Reveal((x, y)) => write!(f, "reveal {x} {y}"),
+            ToServer::Flag((x, y)) => write!(f, "flag {x} {y}"),
+            ToServer::Cursor((x, y)) => write!(f, "cursor {x} {y}"),
+            ToServer::Respawn => write!(f, "respawn"),
+            ToServer::Resync => write!(f, "resync"),
+        }
+    }
+}
+
+impl FromStr for ToServer {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = s.strip_prefix("hello ") {
+            return Ok(ToServer::Hello(clean_name(name)));
+        }
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words.split_first() {
+            Some((&"reveal", rest)) => parse_point(rest).map(ToServer::Reveal),
+            Some((&"flag", rest)) => parse_point(rest).map(ToServer::Flag),
+            Some((&"cursor", rest)) => parse_point(rest).map(ToServer::Cursor),
+            Some((&"respawn", [])) => Some(ToServer::Respawn),
+            Some((&"resync", [])) => Some(ToServer::Resync),
+            _ => None,
+        }.ok_or(())
+    }
+}
+
+impl fmt::Display for ToClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ToClient::Welcome(id) => write!(f, "welcome {id}"),
+            ToClient::Board(bytes) => {
+                f.write_str("board ")?;
+                bytes.iter().try_for_each(|b| write!(f, "{b:02x}"))
+            },
+            ToClient::Revealed((x, y), num) => write!(f, "revealed {x} {y} {num}"),
+            ToClient::Flagged((x, y), flagged) => write!(f, "flagged {x} {y} {flagged}"),
+            ToClient::Mine((x, y)) => write!(f, "mine {x} {y}"),
+            ToClient::Player { id, score, alive, name } => write!(f, "player {id} {score} {alive} {name}"),
+            ToClient::Left(id) => write!(f, "left {id}"),
+            ToClient::Cursor(id, (x, y)) => write!(f, "cursor {id} {x} {y}"),
+            ToClient::Round(round) => write!(f, "round {round}"),
+            ToClient::Cleared(id, time) => write!(f, "cleared {id} {}", time.as_millis()),
+            ToClient::Winner(id) => write!(f, "winner {id}"),
+            ToClient::Full => write!(f, "full"),
+        }
+    }
+}
+
+impl FromStr for ToClient {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(hex) = s.strip_prefix("board ") {
+            if hex.len() % 2 != 0 || !hex.is_ascii() {
+                return Err(());
+            }
+            let bytes = (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i+2], 16).ok()).collect::<Option<_>>();
+            return bytes.map(ToClient::Board).ok_or(());
+        }
+        let words: Vec<&str> = s.splitn(5, ' ').collect();
+        match words.split_first() {
+            Some((&"welcome", rest)) => parse_words(rest).map(|[id]| ToClient::Welcome(id)),
+            Some((&"revealed", [x, y, num])) => Some(ToClient::Revealed(parse_point(&[x, y]).ok_or(())?, num.parse().map_err(drop)?)),
+            Some((&"flagged", [x, y, flagged])) => Some(ToClient::Flagged(parse_point(&[x, y]).ok_or(())?, flagged.parse().map_err(drop)?)),
+            Some((&"mine", rest)) => parse_point(rest).map(ToClient::Mine),
+            Some((&"player", [id, score, alive, name])) => Some(ToClient::Player {
+                id: id.parse().map_err(drop)?,
+                score: score.parse().map_err(drop)?,
+                alive: alive.parse().map_err(drop)?,
+                name: clean_name(name),
+            }),
+            Some((&"left", rest)) => parse_words(rest).map(|[id]| ToClient::Left(id)),
+            Some((&"cursor", [id, x, y])) => Some(ToClient::Cursor(id.parse().map_err(drop)?, parse_point(&[x, y]).ok_or(())?)),
+            Some((&"round", rest)) => parse_words(rest).map(|[round]| ToClient::Round(round)),
+            Some((&"cleared", rest)) => parse_words(rest).map(|[id, millis]| ToClient::Cleared(id as u32, Duration::from_millis(millis))),
+            Some((&"winner", rest)) => parse_words(rest).map(|[id]| ToClient::Winner(id)),
+            Some((&"full", [])) => Some(ToClient::Full),
+            _ => None,
+        }.ok_or(())
+    }
+}
+
+/// Someone playing on the server.
+pub struct Player {
+    pub name: String,
+    /// How many tiles they've revealed since they last died.
+    pub score: usize,
+    pub alive: bool,
+    /// The tile their mouse was last over.
+    pub cursor: Option<Coord>,
+    /// How long they took to clear their board in versus, if they have.
+    pub cleared: Option<Duration>,
+}
+
+impl Player {
+    pub fn new(name: String) -> Self {
+        Self { name, score: 0, alive: true, cursor: None, cleared: None }
+    }
+
+    fn message(&self, id: u32) -> ToClient {
+        ToClient::Player { id, score: self.score, alive: self.alive, name: self.name.clone() }
+    }
+}
+
+/// What the server's threads tell the main loop.
+enum Event {
+    Joined(u32, String, TcpStream),
+    Message(u32, ToServer),
+    Left(u32),
+}
+
+struct Client {
+    player: Player,
+    stream: TcpStream,
+    /// Their own board, in versus.
+    board: Option<Game>,
+}
+
+/// A game of versus, played in rounds.
+struct Versus {
+    /// The board every player starts the round with.
+    start: Field,
+    /// The seed of the first round. Each round after that uses the next number along.
+    seed: u64,
+    round: u64,
+    winner: Option<u32>,
+}
+
+enum Mode {
+    /// Everyone plays on the game in the save file.
+    Shared(Game),
+    Versus(Versus),
+}
+
+struct Server {
+    mode: Mode,
+    clients: BTreeMap<u32, Client>,
+    /// Whether the shared game has changed since it was last saved.
+    unsaved: bool,
+}
+
+/// Write a line to a client. If it can't be written, the connection is closed, and its reader will notice and say that the client left.
+fn send(stream: &mut TcpStream, line: &str) {
+    if writeln!(stream, "{line}").is_err() {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn board_of(field: &Field) -> ToClient {
+    let mut bytes = Vec::new();
+    field.save(&mut bytes).expect("failed to save the board");
+    ToClient::Board(bytes)
+}
+
+impl Server {
+    fn broadcast(&mut self, message: &ToClient) {
+        let line = message.to_string();
+        for client in self.clients.values_mut() {
+            send(&mut client.stream, &line);
+        }
+    }
+
+    fn tell(&mut self, id: u32, message: &ToClient) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            send(&mut client.stream, &message.to_string());
+        }
+    }
+
+    /// A player, along with the game they're playing.
+    fn player(&mut self, id: u32) -> Option<(&mut Player, &mut Game)> {
+        let client = self.clients.get_mut(&id)?;
+        let game = match &mut self.mode {
+            Mode::Shared(game) => game,
+            Mode::Versus(_) => client.board.as_mut()?,
+        };
+        Some((&mut client.player, game))
+    }
+
+    /// Whether nobody can play until someone starts again.
+    fn is_over(&self) -> bool {
+        match &self.mode {
+            Mode::Shared(game) => game.field.is_won(),
+            Mode::Versus(versus) => versus.winner.is_some(),
+        }
+    }
+
+    fn send_board(&mut self, id: u32) {
+        let Some((_, game)) = self.player(id) else { return };
+        let board = board_of(&game.field);
+        self.tell(id, &board);
+    }
+
+    /// Show everyone the changes to a player's board. Only they can see their board in versus.
+    fn board_changed(&mut self, id: u32, changes: &[ToClient]) {
+        let shared = matches!(self.mode, Mode::Shared(_));
+        self.unsaved |= shared;
+        for change in changes {
+            if shared {
+                self.broadcast(change);
+            } else {
+                self.tell(id, change);
+            }
+        }
+    }
+
+    /// Save the shared game if it's changed since it was last saved.
+    fn save(&mut self) {
+        if let Mode::Shared(game) = &mut self.mode && self.unsaved {
+            game.save();
+            self.unsaved = false;
+        }
+    }
+
+    fn join(&mut self, id: u32, name: String, stream: TcpStream) {
+        let name = if name.is_empty() { format!("player {id}") } else { name };
+        eprintln!("{name} joined");
+        let board = match &self.mode {
+            Mode::Shared(_) => None,
+            Mode::Versus(versus) => Some(Game::remote(versus.start.clone())),
+        };
+        self.clients.insert(id, Client { player: Player::new(name), stream, board });
+
+        // the board has to come straight after the welcome, as that's what `Connection::open` waits for
+        self.tell(id, &ToClient::Welcome(id));
+        self.send_board(id);
+        if let Mode::Versus(versus) = &self.mode {
+            let round = versus.round;
+            self.tell(id, &ToClient::Round(round));
+        }
+        let mut news = Vec::new();
+        for (&other, client) in self.clients.iter().filter(|&(&other, _)| other != id) {
+            news.push(client.player.message(other));
+            news.extend(client.player.cursor.filter(|_| client.board.is_none()).map(|point| ToClient::Cursor(other, point)));
+            news.extend(client.player.cleared.map(|time| ToClient::Cleared(other, time)));
+        }
+        if let Mode::Versus(Versus { winner: Some(winner), .. }) = self.mode {
+            news.push(ToClient::Winner(winner));
+        }
+        for message in news {
+            self.tell(id, &message);
+        }
+        let message = self.clients[&id].player.message(id);
+        self.broadcast(&message);
+    }
+
+    fn leave(&mut self, id: u32) {
+        if let Some(client) = self.clients.remove(&id) {
+            eprintln!("{} left", client.player.name);
+            self.broadcast(&ToClient::Left(id));
+            self.check_winner();
+            // nothing is going to change until someone joins, so there's no reason to wait
+            if self.clients.is_empty() {
+                self.save();
+            }
+        }
+    }
+
+    /// End the round of versus if someone has cleared their board, or if everyone is dead, in which case whoever revealed the most wins.
+    fn check_winner(&mut self) {
+        let Mode::Versus(versus) = &mut self.mode else { return };
+        if versus.winner.is_some() {
+            return;
+        }
+        let cleared = self.clients.iter().find(|(_, client)| client.player.cleared.is_some());
+        let everyone_dead = self.clients.values().all(|client| !client.player.alive);
+        let best = self.clients.iter().max_by_key(|(_, client)| client.player.score).filter(|_| everyone_dead);
+        let Some((&winner, client)) = cleared.or(best) else { return };
+        eprintln!("{} won round {}", client.player.name, versus.round + 1);
+        versus.winner = Some(winner);
+        self.broadcast(&ToClient::Winner(winner));
+    }
+
+    /// Start again with everyone alive, on a cleared board, or in versus, on the board for the next round.
+    fn start_again(&mut self) {
+        match &mut self.mode {
+            Mode::Shared(game) => {
+                game.reset();
+                let board = board_of(&game.field);
+                self.unsaved = true;
+                self.broadcast(&board);
+            },
+            Mode::Versus(versus) => {
+                versus.round += 1;
+                versus.winner = None;
+                versus.start.set_seed(Some(versus.seed.wrapping_add(versus.round)));
+                for client in self.clients.values_mut() {
+                    client.board = Some(Game::remote(versus.start.clone()));
+                }
+                let round = versus.round;
+                self.broadcast(&ToClient::Round(round));
+            },
+        }
+        let versus = matches!(self.mode, Mode::Versus(_));
+        let ids: Vec<u32> = self.clients.keys().copied().collect();
+        for id in ids {
+            let player = &mut self.clients.get_mut(&id).unwrap().player;
+            player.score = 0;
+            player.alive = true;
+            player.cleared = None;
+            let message = player.message(id);
+            if versus {
+                self.send_board(id);
+            }
+            self.broadcast(&message);
+        }
+    }
+
+    fn handle(&mut self, id: u32, message: ToServer) {
+        let over = self.is_over();
+        let versus = matches!(self.mode, Mode::Versus(_));
+        let Some((player, game)) = self.player(id) else { return };
+        match message {
+            ToServer::Hello(_) => {},
+            ToServer::Reveal(point) => {
+                if !player.alive || over {
+                    return;
+                }
+                let reveal = game.spread(point);
+                if reveal.revealed.is_empty() && reveal.death.is_none() {
+                    return;
+                }
+                player.score += reveal.revealed.len();
+                player.alive = reveal.death.is_none();
+                if versus && game.field.is_won() {
+                    player.cleared = Some(game.field.time_elapsed());
+                }
+                // the judge can't take back a mine on a shared board, so it's recorded as one and flagged for everyone else to see
+                let marked = reveal.death.filter(|_| !versus);
+                if let Some(death) = marked {
+                    game.field.mark_mine(death);
+                    game.field.toggle_flag(death);
+                }
+                let (message, cleared) = (player.message(id), player.cleared);
+                let changes: Vec<_> = reveal.revealed.into_iter().map(|(point, num)| ToClient::Revealed(point, num))
+                    .chain(marked.into_iter().flat_map(|death| [ToClient::Mine(death), ToClient::Flagged(death, true)]))
+                    .collect();
+                self.board_changed(id, &changes);
+                self.broadcast(&message);
+                if let Some(time) = cleared {
+                    self.broadcast(&ToClient::Cleared(id, time));
+                }
+                self.check_winner();
+            },
+            ToServer::Flag(point) => if player.alive && game.flag(point) {
+                let flagged = game.field.get(point) == Some(Cell::Hidden(true));
+                self.board_changed(id, &[ToClient::Flagged(point, flagged)]);
+            },
+            ToServer::Cursor(point) => {
+                player.cursor = Some(point);
+                // everyone's board is different in versus, so where someone is looking on theirs doesn't mean anything
+                if !versus {
+                    let line = ToClient::Cursor(id, point).to_string();
+                    for (_, client) in self.clients.iter_mut().filter(|&(&other, _)| other != id) {
+                        send(&mut client.stream, &line);
+                    }
+                }
+            },
+            ToServer::Resync => self.send_board(id),
+            ToServer::Respawn if over => self.start_again(),
+            // dying in versus is for the rest of the round
+            ToServer::Respawn => if !player.alive && !versus {
+                player.score = 0;
+                player.alive = true;
+                let message = player.message(id);
+                self.broadcast(&message);
+            },
+        }
+    }
+}
+
+/// Read a line from a client. Returns None if they've gone, gone quiet for too long or sent a line longer than `MAX_LINE`.
+fn read_line(reader: &mut BufReader<TcpStream>) -> Option<String> {
+    let mut line = Vec::new();
+    reader.by_ref().take(MAX_LINE).read_until(b'\n', &mut line).ok()?;
+    if line.pop() != Some(b'\n') {
+        return None;
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Some(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Pass on what a client says until it disconnects. Nothing happens until it's said hello.
+fn read_client(id: u32, stream: TcpStream, events: Sender<Event>) {
+    let Ok(writer) = stream.try_clone() else { return };
+    if stream.set_read_timeout(Some(HELLO_TIMEOUT)).is_err() {
+        return;
+    }
+    let mut reader = BufReader::new(stream);
+    let hello = read_line(&mut reader).and_then(|line| line.parse().ok());
+    let Some(ToServer::Hello(name)) = hello else { return };
+    if reader.get_ref().set_read_timeout(Some(IDLE_TIMEOUT)).is_err() || events.send(Event::Joined(id, name, writer)).is_err() {
+        return;
+    }
+    while let Some(line) = read_line(&mut reader) {
+        // anything that doesn't make sense is ignored
+        if let Ok(message) = line.parse() && events.send(Event::Message(id, message)).is_err() {
+            return;
+        }
+    }
+    let _ = events.send(Event::Left(id));
+}
+
+/// Share the game in the save file with anyone who connects to the address, or in versus, race on new boards with them.
+/// Runs until it's killed. A shared game is saved every `SAVE_INTERVAL` while it's changing, and as soon as everyone has left.
+pub fn serve(args: Args, save_path: PathBuf, address: &str, versus: bool) -> io::Result<()> {
+    let mode = if versus {
+        let seed = args.seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64));
+        let mut start = game::new_field(&args);
+        start.set_seed(Some(seed));
+        Mode::Versus(Versus { start, seed, round: 0, winner: None })
+    } else {
+        Mode::Shared(Game::open(&args, SaveSlot::new(save_path), false))
+    };
+    let listener = TcpListener::bind(address)?;
+    eprintln!("serving on {}", listener.local_addr()?);
+
+    let (sender, events) = mpsc::channel();
+    thread::spawn(move || {
+        let connected = Arc::new(AtomicUsize::new(0));
+        for (id, stream) in (0..).zip(listener.incoming()) {
+            let Ok(mut stream) = stream else { continue };
+            if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+                continue;
+            }
+            // only this thread adds to the count, so it can't go over between checking it and adding to it
+            if connected.load(Ordering::Relaxed) >= MAX_CLIENTS {
+                send(&mut stream, &ToClient::Full.to_string());
+                continue;
+            }
+            connected.fetch_add(1, Ordering::Relaxed);
+            let (sender, connected) = (sender.clone(), connected.clone());
+            thread::spawn(move || {
+                read_client(id, stream, sender);
+                connected.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    });
+
+    let mut server = Server { mode, clients: BTreeMap::new(), unsaved: false };
+    let mut last_save = Instant::now();
+    loop {
+        match events.recv_timeout(SAVE_INTERVAL.saturating_sub(last_save.elapsed())) {
+            Ok(Event::Joined(id, name, stream)) => server.join(id, name, stream),
+            Ok(Event::Message(id, message)) => server.handle(id, message),
+            Ok(Event::Left(id)) => server.leave(id),
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if last_save.elapsed() >= SAVE_INTERVAL {
+            server.save();
+            last_save = Instant::now();
+        }
+    }
+    server.save();
+    Ok(())
+}
+
+/// A client's connection to a server.
+pub struct Connection {
+    stream: TcpStream,
+    /// Everything the server says after the welcome. Disconnects when the server goes away.
+    pub messages: Receiver<ToClient>,
+}
+
+impl Connection {
+    /// Join the server at an address, returning the connection, the id we were given and the board.
+    pub fn open(address: &str, name: &str) -> io::Result<(Self, u32, Field)> {
+        let stream = TcpStream::connect(address)?;
+        let mut writer = stream.try_clone()?;
+        writeln!(writer, "{}", ToServer::Hello(clean_name(name)))?;
+        let mut lines = BufReader::new(stream).lines();
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "the server isn't speaking minefair");
+        let mut next = || lines.next().unwrap_or_else(|| Err(io::ErrorKind::UnexpectedEof.into()));
+        let id = match next()?.parse() {
+            Ok(ToClient::Welcome(id)) => id,
+            Ok(ToClient::Full) => return Err(io::Error::other("the server is full")),
+            _ => return Err(invalid()),
+        };
+        let Ok(ToClient::Board(bytes)) = next()?.parse() else { return Err(invalid()) };
+        let field = Field::load(&mut &bytes[..]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for line in lines {
+                let Ok(line) = line else { break };
+                if let Ok(message) = line.parse() && sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok((Self { stream: writer, messages }, id, field))
+    }
+
+    /// Tell the server something. If the connection is gone, `messages` will say so.
+    pub fn send(&mut self, message: ToServer) {
+        let _ = writeln!(self.stream, "{message}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use minefair_field::{Judge, Tolerance};
+    use super::*;
+
+    /// Both ends of a connection, the server's first.
+    fn connection() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(WRITE_TIMEOUT)).unwrap();
+        (listener.accept().unwrap().0, client)
+    }
+
+    /// A shared game of the board in the text format with one player in it, and the other end of the player's connection.
+    fn shared(board: &str) -> (Server, BufReader<TcpStream>) {
+        let field = Field::import_text(&mut board.as_bytes()).unwrap();
+        let (stream, client) = connection();
+        let mut server = Server { mode: Mode::Shared(Game::remote(field)), clients: BTreeMap::new(), unsaved: false };
+        server.join(0, "someone".to_owned(), stream);
+        (server, BufReader::new(client))
+    }
+
+    /// Read what the server says until it says something that passes the test.
+    fn wait_for(client: &mut BufReader<TcpStream>, test: impl Fn(ToClient) -> bool) {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            assert_ne!(client.read_line(&mut line).unwrap(), 0, "the server hung up");
+            if line.trim_end().parse().is_ok_and(&test) {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn shared_deaths() {
+        // the strict judge kills on anything that might be a mine, so the tile is only a mine because the judge said so
+        let (mut server, mut client) = shared("judge strict\nbounds 4x1\n\n#1##\n");
+        let Mode::Shared(game) = &server.mode else { unreachable!() };
+        let (mine, _) = game.field.risks().iter().find(|&(_, risk)| risk == 0.5).unwrap();
+
+        server.handle(0, ToServer::Reveal(mine));
+        let Mode::Shared(game) = &server.mode else { unreachable!() };
+        assert_eq!(game.field.get(mine), Some(Cell::Hidden(true)));
+        assert_eq!(game.field.risks().get(mine), Some(1.0));
+        assert!(!server.clients[&0].player.alive);
+        wait_for(&mut client, |message| matches!(message, ToClient::Mine(point) if point == mine));
+        wait_for(&mut client, |message| matches!(message, ToClient::Flagged(point, true) if point == mine));
+
+        // nobody can be told by a kinder judge that it was safe all along, even once it's been unflagged
+        let Mode::Shared(game) = &mut server.mode else { unreachable!() };
+        game.field.set_rules(Judge::Kind, false, Tolerance::default());
+        server.handle(0, ToServer::Respawn);
+        server.handle(0, ToServer::Reveal(mine));
+        assert!(server.clients[&0].player.alive);
+        server.handle(0, ToServer::Flag(mine));
+        wait_for(&mut client, |message| matches!(message, ToClient::Flagged(point, false) if point == mine));
+        server.handle(0, ToServer::Reveal(mine));
+        let Mode::Shared(game) = &server.mode else { unreachable!() };
+        assert_eq!(game.field.get(mine), Some(Cell::Hidden(true)));
+        assert!(!server.clients[&0].player.alive);
+        server.handle(0, ToServer::Respawn);
+
+        // and the rest of the board was worked out with the mine in it
+        let Mode::Shared(game) = &server.mode else { unreachable!() };
+        let (safe, _) = game.field.risks().iter().find(|&(_, risk)| risk == 0.0).unwrap();
+        server.handle(0, ToServer::Reveal(safe));
+        wait_for(&mut client, |message| matches!(message, ToClient::Revealed(point, _) if point == safe));
+        assert!(server.unsaved);
+    }
+
+    #[test]
+    fn resync() {
+        let (mut server, mut client) = shared("bounds 4x1\n\n1#1#\n");
+        wait_for(&mut client, |message| matches!(message, ToClient::Board(_)));
+        server.handle(0, ToServer::Resync);
+        wait_for(&mut client, |message| matches!(message, ToClient::Board(bytes) if Field::load(&mut &bytes[..]).is_ok()));
+    }
+
+    #[test]
+    fn far_away() {
+        // finding the neighbours of a tile this far out would overflow
+        for line in ["reveal 9223372036854775807 0", "flag 0 -9223372036854775808", "cursor 0 2305843009213693952"] {
+            assert!(line.parse::<ToServer>().is_err(), "{line}");
+        }
+        assert!("reveal -2305843009213693951 2305843009213693951".parse::<ToServer>().is_ok());
+    }
+
+    #[test]
+    fn long_lines() {
+        let (stream, mut client) = connection();
+        let (sender, events) = mpsc::channel();
+        let reader = thread::spawn(move || read_client(0, stream, sender));
+        writeln!(client, "hello someone\ncursor 1 2").unwrap();
+        client.write_all(&[b'a'; MAX_LINE as usize]).unwrap();
+        reader.join().unwrap();
+        let events: Vec<_> = events.into_iter().collect();
+        assert!(matches!(&events[..], [Event::Joined(0, name, _), Event::Message(0, ToServer::Cursor((1, 2))), Event::Left(0)] if name == "someone"));
+    }
+
+    #[test]
+    fn joining_versus() {
+        let mut start = Field::import_text(&mut "bounds 9x9\n\n".as_bytes()).unwrap();
+        start.set_seed(Some(1));
+        let mut server = Server { mode: Mode::Versus(Versus { start, seed: 1, round: 2, winner: None }), clients: BTreeMap::new(), unsaved: false };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let client = thread::spawn(move || Connection::open(&address, "someone"));
+        let (stream, _) = listener.accept().unwrap();
+        let Some(ToServer::Hello(name)) = read_line(&mut BufReader::new(stream.try_clone().unwrap())).and_then(|line| line.parse().ok()) else {
+            panic!("the client didn't say hello");
+        };
+        server.join(0, name, stream);
+
+        let (connection, id, field) = client.join().unwrap().unwrap();
+        assert_eq!(id, 0);
+        assert_eq!(field.size(), Some((9, 9)));
+        let round = connection.messages.recv_timeout(WRITE_TIMEOUT).unwrap();
+        assert!(matches!(round, ToClient::Round(2)));
+    }
+}
@@ -0,0 +1,283 @@
+//! A line-based protocol over stdin and stdout, for bots that aren't written in Rust.
+//!
+//! After a `minefair 1` greeting, each line read is a command, and each command gets some lines of data back followed by one
+//! line that's `ok`, `won`, `dead X Y` or `error MESSAGE`. The commands are documented in the README.
+
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use minefair_field::Cell;
+use crate::game::{Coord, Game, MAX_COORD, Reveal};
+use crate::saves::SaveSlot;
+use crate::Args;
+
+/// Bumped whenever the protocol changes in a way that could break a bot.
+const VERSION: u32 = 1;
+/// The most tiles `cells` and `risks` will look at in one go.
+const MAX_AREA: usize = 1 << 16;
+
+enum Command {
+    Reveal(Coord),
+    Chord(Coord),
+    Flag(Coord),
+    Cells(Coord, Coord),
+    Risks(Coord, Coord),
+    Info,
+    Save,
+    New,
+    Quit,
+}
+
+fn parse_numbers<const N: usize>(args: &[&str]) -> Result<[isize; N], String> {
+    if args.len() != N {
+        return Err(format!("expected {N} numbers"));
+    }
+    let mut numbers = [0; N];
+    for (n, arg) in numbers.iter_mut().zip(args) {
+        *n = arg.parse().map_err(|_| format!("invalid number {arg}"))?;
+        if !(-MAX_COORD..=MAX_COORD).contains(n) {
+            return Err(format!("{arg} is too far from the middle of the board"));
+        }
+    }
+    Ok(numbers)
+}
+
+fn parse_rect(args: &[&str]) -> Result<(Coord, Coord), String> {
+    let [x1, y1, x2, y2] = parse_numbers(args)?;
+    let (lo, hi) = ((x1.min(x2), y1.min(y2)), (x1.max(x2), y1.max(y2)));
+    let area = hi.0.abs_diff(lo.0).saturating_add(1).saturating_mul(hi.1.abs_diff(lo.1).saturating_add(1));
+    if area > MAX_AREA {
+        return Err(format!("rectangle is too big, at most {MAX_AREA} tiles can be asked for at once"));
+    }
+    Ok((lo, hi))
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else { return Err("empty command".to_owned()) };
+        let point = || parse_numbers(args).map(|[x, y]| (x, y));
+        let nothing = |command| if args.is_empty() { Ok(command) } else { Err(format!("{name} takes no arguments")) };
+        match name {
+            "reveal" => point().map(Command::Reveal),
+            "chord" => point().map(Command::Chord),
+            "flag" => point().map(Command::Flag),
+            "cells" => parse_rect(args).map(|(lo, hi)| Command::Cells(lo, hi)),
+            "risks" => parse_rect(args).map(|(lo, hi)| Command::Risks(lo, hi)),
+            "info" => nothing(Command::Info),
+            "save" => nothing(Command::Save),
+            "new" => nothing(Command::New),
+            "quit" => nothing(Command::Quit),
+            _ => Err(format!("unknown command {name}")),
+        }
+    }
+}
+
+/// How a tile is written by `cells`, the same as in text boards except that mines are never given away.
+fn cell_char(cell: Option<Cell>) -> char {
+    match cell {
+        Some(Cell::Revealed(n)) => char::from_digit(n as u32, 36).unwrap(),
+        Some(Cell::Hidden(true)) => 'F',
+        Some(Cell::Hidden(false)) => '#',
+        None => '.',
+    }
+}
+
+struct Session {
+    game: Game,
+    cheat: bool,
+    autosave: bool,
+}
+
+impl Session {
+    fn ending(&self, reveal: &Reveal) -> String {
+        match reveal.death {
+            Some((x, y)) => format!("dead {x} {y}"),
+            None if self.game.field.is_won() => "won".to_owned(),
+            None => "ok".to_owned(),
+        }
+    }
+
+    fn reveal(&mut self, point: Coord, out: &mut Vec<String>) -> Result<String, String> {
+        let reveal = self.game.reveal(point);
+        for &((x, y), n) in &reveal.revealed {
+            out.push(format!("revealed {x} {y} {n}"));
+        }
+        if self.autosave && !self.game.dead {
+            self.game.save();
+        }
+        Ok(self.ending(&reveal))
+    }
+
+    /// Run a command, adding its lines of data to `out` and returning the line that ends the response.
+    fn run(&mut self, command: Command, out: &mut Vec<String>) -> Result<String, String> {
+        let field = &self.game.field;
+        match command {
+            Command::Reveal(_) | Command::Chord(_) | Command::Flag(_) if self.game.is_over() => Err("the game is over".to_owned()),
+            Command::Reveal(point) => match field.get(point) {
+                Some(Cell::Hidden(false)) => self.reveal(point, out),
+                Some(Cell::Hidden(true)) => Err("that tile is flagged".to_owned()),
+                Some(Cell::Revealed(_)) => Err("that tile is already revealed".to_owned()),
+                None => Err("that tile isn't on the board".to_owned()),
+            },
+            Command::Chord(point) => {
+                let flags = field.adjacents(point).filter(|&p| field.get(p) == Some(Cell::Hidden(true))).count();
+                match field.get(point) {
+                    Some(Cell::Revealed(n)) if n as usize == flags => self.reveal(point, out),
+                    Some(Cell::Revealed(_)) => Err("the number of flags around that tile doesn't match its number".to_owned()),
+                    _ => Err("that tile isn't revealed".to_owned()),
+                }
+            },
+            Command::Flag((x, y)) => {
+                if !self.game.flag((x, y)) {
+                    return Err("that tile can't be flagged".to_owned());
+                }
+                out.push(format!("flagged {x} {y} {}", self.game.field.get((x, y)) == Some(Cell::Hidden(true))));
+                Ok("ok".to_owned())
+            },
+            Command::Cells((lx, ly), (hx, hy)) => {
+                for y in ly..=hy {
+                    let row: String = (lx..=hx).map(|x| cell_char(field.get((x, y)))).collect();
+                    out.push(format!("row {y} {row}"));
+                }
+                Ok("ok".to_owned())
+            },
+            Command::Risks(..) if !self.cheat => Err("risks are only available with --cheat".to_owned()),
+            Command::Risks(lo, (hx, hy)) => {
+                let mut risks: Vec<_> = field.risks().in_rect(lo, (hx.saturating_add(1), hy.saturating_add(1))).collect();
+                risks.sort_by_key(|&((x, y), _)| (y, x));
+                for ((x, y), risk) in risks {
+                    out.push(format!("risk {x} {y} {risk}"));
+                }
+                Ok("ok".to_owned())
+            },
+            Command::Info => {
+                let bounds = field.size().map_or("none".to_owned(), |(w, h)| format!("{w}x{h}"));
+                let state = if self.game.dead { "dead" } else if field.is_won() { "won" } else { "playing" };
+                out.push(format!(
+                    "info judge {} density {} bounds {bounds} topology {} wrap {} revealed {} state {state}",
+                    field.judge(), field.density(), field.topology(), field.wraps(), field.cells_revealed(),
+                ));
+                Ok("ok".to_owned())
+            },
+            Command::Save => {
+                self.game.save();
+                Ok("ok".to_owned())
+            },
+            Command::New if !self.game.is_over() => Err("the game isn't over".to_owned()),
+            Command::New => {
+                self.game.reset();
+                Ok("ok".to_owned())
+            },
+            Command::Quit => Ok("ok".to_owned()),
+        }
+    }
+
+    /// Answer commands from the input until `quit` or the end of it. Stops early if the input can't be read or the output can't be written.
+    fn serve(&mut self, mut input: impl BufRead, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "minefair {VERSION}")?;
+        out.flush()?;
+
+        let mut bytes = Vec::new();
+        loop {
+            bytes.clear();
+            if input.read_until(b'\n', &mut bytes)? == 0 {
+                return Ok(());
+            }
+            let command = match std::str::from_utf8(&bytes) {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => Command::parse(line),
+                Err(_) => Err("the command isn't valid UTF-8".to_owned()),
+            };
+            let quit = matches!(command, Ok(Command::Quit));
+            let mut data = Vec::new();
+            let ending = command.and_then(|command| self.run(command, &mut data)).unwrap_or_else(|message| format!("error {message}"));
+            for line in data {
+                writeln!(out, "{line}")?;
+            }
+            writeln!(out, "{ending}")?;
+            out.flush()?;
+            if quit {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Play the game in the save file with commands from stdin until `quit` or the end of the input.
+/// The game is saved however that happens, even if stdin or stdout stop working, which is then returned as an error.
+pub fn run(args: Args, save_path: PathBuf) -> io::Result<()> {
+    let game = Game::open(&args, SaveSlot::new(save_path), false);
+    let mut session = Session { game, cheat: args.cheat, autosave: args.autosave };
+    let result = session.serve(io::stdin().lock(), io::stdout().lock());
+
+    session.game.save();
+    if let Some(path) = &args.audit_log {
+        session.game.write_audit_log(path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use minefair_field::Field;
+    use super::*;
+
+    /// A session on a board in the text format, which is never saved.
+    fn session(board: &str) -> Session {
+        Session { game: Game::remote(Field::import_text(&mut board.as_bytes()).unwrap()), cheat: false, autosave: false }
+    }
+
+    /// Send some lines to a session, returning what it says back after the greeting.
+    fn talk(session: &mut Session, input: &[u8]) -> Vec<String> {
+        let mut out = Vec::new();
+        session.serve(input, &mut out).unwrap();
+        String::from_utf8(out).unwrap().lines().skip(1).map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn parsing() {
+        assert!(matches!(Command::parse("reveal 3 -4"), Ok(Command::Reveal((3, -4)))));
+        assert!(matches!(Command::parse("  cells 2 2 0 -1\n"), Ok(Command::Cells((0, -1), (2, 2)))));
+        assert!(matches!(Command::parse("quit"), Ok(Command::Quit)));
+        for line in ["", "reveal 1", "reveal 1 2 3", "reveal x 2", "info now", "dance", "chord 9223372036854775807 0", "cells 0 0 1000 1000"] {
+            assert!(Command::parse(line).is_err(), "{line:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let mut session = session("bounds 4x1\norigin -2 -1\n\n1#1#\n");
+        let said = talk(&mut session, b"cells -2 -1 1 -1\nrisks -2 -1 1 -1\nflag -1 -1\n\nreveal -1 -1\n\xff\nnew\nreveal 1 -1\nreveal -1 -1\nquit\ninfo\n");
+        assert_eq!(said, [
+            "row -1 1#1#", "ok",
+            "error risks are only available with --cheat",
+            "flagged -1 -1 true", "ok",
+            "error that tile is flagged",
+            "error the command isn't valid UTF-8",
+            "error the game isn't over",
+            "revealed 1 -1 0", "won",
+            "error the game is over",
+            "ok",
+        ]);
+
+        let mut session = self::session("bounds 4x1\norigin -2 -1\n\n1#1#\n");
+        assert_eq!(talk(&mut session, b"reveal -1 -1\nnew\ncells -2 -1 1 -1"), ["dead -1 -1", "ok", "row -1 ####", "ok"]);
+    }
+
+    #[test]
+    fn closed_output() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut session = session("bounds 4x1\norigin -2 -1\n\n1#1#\n");
+        assert_eq!(session.serve(&b"reveal 1 -1\n"[..], Closed).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,115 @@
+use std::cell::Cell;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use minefair_field::{Field, LoadError};
+
+/// How many old saves to keep around.
+const BACKUPS: usize = 3;
+
+/// A save file on disk, along with its backups.
+///
+/// Saves are written to a temporary file first and then moved over the old save, so the save file always holds a complete game.
+/// The first time a slot is written to, the save that was there becomes `<path>.1`, the one before that `<path>.2` and so on.
+/// Writing again doesn't touch the backups, so they hold the game as it was at the start of each of the last few sessions.
+pub struct SaveSlot {
+    path: PathBuf,
+    rotated: Cell<bool>,
+}
+
+impl SaveSlot {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, rotated: Cell::new(false) }
+    }
+
+    fn with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut name = OsString::from(self.path.as_os_str());
+        name.push(".");
+        name.push(suffix);
+        name.into()
+    }
+
+    fn backup_path(&self, n: usize) -> PathBuf {
+        self.with_suffix(&n.to_string())
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// When the save was last written.
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        fs::metadata(&self.path)?.modified()
+    }
+
+    pub fn load(&self) -> Result<Field, LoadError> {
+        Field::load(&mut File::open(&self.path)?)
+    }
+
+    pub fn write(&self, field: &Field) -> io::Result<()> {
+        let temp_path = self.with_suffix("tmp");
+        let mut temp = BufWriter::new(File::create(&temp_path)?);
+        field.save(&mut temp).map_err(io::Error::other)?;
+        temp.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        if !self.rotated.get() && self.exists() {
+            self.rotate_backups()?;
+            self.rotated.set(true);
+        }
+        fs::rename(temp_path, &self.path)?;
+        self.sync_dir()
+    }
+
+    /// Make sure that renames in the save's directory survive a crash. This can only be done on Unix, where directories can be opened like files.
+    fn sync_dir(&self) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            let dir = self.path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Shift every backup along by one, then copy the current save into the first slot.
+    fn rotate_backups(&self) -> io::Result<()> {
+        for n in (1..BACKUPS).rev() {
+            let from = self.backup_path(n);
+            if from.exists() {
+                fs::rename(from, self.backup_path(n + 1))?;
+            }
+        }
+        let first = self.backup_path(1);
+        // a hard link is cheaper, but not every filesystem has them
+        if fs::hard_link(&self.path, &first).is_err() {
+            fs::copy(&self.path, &first)?;
+        }
+        Ok(())
+    }
+
+    /// The newest backup that loads successfully.
+    pub fn latest_backup(&self) -> Option<(PathBuf, Field)> {
+        (1..=BACKUPS).map(|n| self.backup_path(n)).find_map(|path| {
+            let field = Field::load(&mut File::open(&path).ok()?).ok()?;
+            Some((path, field))
+        })
+    }
+
+    /// Move a save that couldn't be loaded out of the way, so that it isn't overwritten or rotated into the backups.
+    /// Returns where it was moved to.
+    pub fn set_aside(&self) -> io::Result<PathBuf> {
+        let aside = self.with_suffix("damaged");
+        fs::rename(&self.path, &aside)?;
+        Ok(aside)
+    }
+}
+
+/// Ask a yes or no question on the terminal. Anything but a yes is a no.
+pub fn confirm(question: &str) -> bool {
+    eprint!("{question} [y/N] ");
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
@@ -1,17 +1,26 @@
-use std::io::{stdout, Write, Seek, Result};
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::io::{stdout, Write, Result};
+use std::path::PathBuf;
 use std::fmt::Display;
-use std::collections::VecDeque;
 use std::panic;
-use std::time::{Duration, Instant};
+use std::sync::mpsc::TryRecvError;
+use std::time::{Duration, Instant, SystemTime};
 use crossterm::{queue, terminal, cursor};
 use crossterm::event::{Event, KeyCode, MouseEventKind, MouseEvent, MouseButton, read, poll, EnableMouseCapture, DisableMouseCapture, KeyModifiers};
 use crossterm::style::Stylize;
 
-use minefair_field::{Field, Cell, adjacents};
+use minefair_field::{Action, Bot, Cell, Field, Judge, Strategy, Topology};
 use crate::options::{Theme, IconSet};
+use crate::game::{Coord, Game};
+use crate::net::{Connection, Player, ToClient, ToServer};
+use crate::saves::SaveSlot;
 use crate::Args;
 
+/// How long autoplay waits between moves.
+const AUTOPLAY_DELAY: Duration = Duration::from_millis(150);
+/// How often to check for changes to a game that's being played somewhere else.
+const REMOTE_POLL: Duration = Duration::from_millis(50);
+
 #[derive(PartialEq)]
 enum DisplayMode {
     Normal,
@@ -19,8 +28,101 @@ enum DisplayMode {
     Judge,
 }
 
+/// The settings menu, holding the settings as they'll be once they're applied.
+struct Menu {
+    judge: Judge,
+    solvable: bool,
+    /// Which setting is selected.
+    selected: usize,
+}
+
+impl Menu {
+    const SETTINGS: usize = 2;
+    const WIDTH: usize = 32;
+
+    fn change(&mut self, step: isize) {
+        match self.selected {
+            0 => {
+                let i = Judge::ALL.iter().position(|&j| j == self.judge).unwrap();
+                self.judge = Judge::ALL[(i as isize + step).rem_euclid(Judge::ALL.len() as isize) as usize];
+            },
+            _ => self.solvable = !self.solvable,
+        }
+    }
+
+    fn lines(&self) -> [String; 6] {
+        let setting = |i: usize, name: &str, value: &str| {
+            format!(" {} {name:<9} < {value} >", if self.selected == i { '>' } else { ' ' })
+        };
+        [
+            " settings".to_owned(),
+            setting(0, "judge", self.judge.name()),
+            setting(1, "solvable", if self.solvable { "yes" } else { "no" }),
+            String::new(),
+            " arrow keys to change".to_owned(),
+            " enter to apply, esc to cancel".to_owned(),
+        ]
+    }
+}
+
+/// A game on someone else's server.
+struct Remote {
+    connection: Connection,
+    /// Which of the players is us.
+    id: u32,
+    players: BTreeMap<u32, Player>,
+    /// The tile our cursor was last said to be over.
+    cursor: Option<Coord>,
+    /// The round being played, if this is versus.
+    round: Option<u64>,
+    /// Who won the round.
+    winner: Option<u32>,
+    /// Whether we've asked for the whole board again and are waiting for it.
+    resyncing: bool,
+}
+
+impl Remote {
+    /// The initial of another player whose cursor is over a tile.
+    fn marker(&self, p: Coord) -> Option<char> {
+        self.players.iter()
+            .find(|&(&id, player)| id != self.id && player.cursor == Some(p))
+            .map(|(_, player)| player.name.chars().next().map_or('?', |c| c.to_ascii_uppercase()))
+    }
+
+    fn me(&self) -> Option<&Player> {
+        self.players.get(&self.id)
+    }
+
+    /// Ask for the whole board again, because our copy stopped matching the server's. Changes are ignored until it arrives.
+    fn resync(&mut self) {
+        self.resyncing = true;
+        self.connection.send(ToServer::Resync);
+    }
+}
+
+/// A save file being watched while someone else plays it.
+struct Watched {
+    slot: SaveSlot,
+    /// When the game being shown was saved.
+    modified: Option<SystemTime>,
+}
+
+impl Watched {
+    /// The game in the save file, if it's been saved since it was last looked at.
+    fn changed(&mut self) -> Option<Field> {
+        let modified = self.slot.modified().ok();
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        // if it can't be loaded, it's tried again next time
+        let field = self.slot.load().ok()?;
+        self.modified = modified;
+        Some(field)
+    }
+}
+
 struct Camera {
-    field: Field,
+    game: Game,
     w: u16,
     h: u16,
     x: isize,
@@ -29,12 +131,19 @@ struct Camera {
     row: u16,
     mode: DisplayMode,
     cheat: bool,
-    dead: bool,
     theme: Theme,
     iconset: IconSet,
-    save_file: File,
     blink: bool,
-    last_time_pass: Option<Instant>,
+    menu: Option<Menu>,
+    /// Whether the judge's decisions are being shown.
+    log_open: bool,
+    bot: Bot,
+    /// The strategy playing the game, if autoplay is on.
+    autoplay: Option<Box<dyn Strategy + Send>>,
+    /// The server being played on, if the game isn't ours.
+    remote: Option<Remote>,
+    /// The save file being watched, if we're only watching.
+    watched: Option<Watched>,
 }
 
 impl Camera {
@@ -46,30 +155,43 @@ impl Camera {
         }
     }
 
-    fn new(args: Args, save_file: File, (w, h): (u16, u16)) -> Self {
+    fn new(args: &Args, game: Game, remote: Option<Remote>, (w, h): (u16, u16)) -> Self {
         Self {
-            field: Field::new(args.density, args.judge, args.solvable, args.bounds),
+            game,
             w, h,
             x: -(w as isize) / 2, y: -(h as isize) / 2,
             col: u16::MAX, row: u16::MAX,
             mode: Self::default_mode(args.cheat),
             cheat: args.cheat,
-            dead: false,
             theme: args.theme.theme(),
             iconset: args.iconset.iconset(),
-            save_file,
             blink: false,
-            last_time_pass: None,
+            menu: None,
+            log_open: false,
+            bot: args.bot,
+            autoplay: None,
+            remote,
+            watched: None,
         }
     }
 
+    /// Whether the game is being played somewhere else, so that only some things can be done to it from here.
+    fn elsewhere(&self) -> bool {
+        self.remote.is_some() || self.watched.is_some()
+    }
+
     fn reset(&mut self) {
-        self.field.clear();
+        if let Some(remote) = &mut self.remote {
+            // the server says when we're back
+            remote.connection.send(ToServer::Respawn);
+            return;
+        }
+        self.game.reset();
         self.x = -(self.w as isize) / 2;
         self.y = -(self.h as isize) / 2;
         self.mode = Self::default_mode(self.cheat);
-        self.dead = false;
-        self.last_time_pass = None;
+        self.log_open = false;
+        self.autoplay = None;
         self.draw_entire_board();
     }
 
@@ -86,22 +208,34 @@ impl Camera {
         self.col += 1;
     }
 
-    fn show_cell(&mut self, p@(x, y): (isize, isize)) {
-        let cell = self.field.get(p);
-        let (col, row) = (x*3-self.x, y-self.y);
+    /// How many columns a cell takes up. Hex cells are wider, so that shifted rows can sit exactly halfway between the cells above them.
+    fn cell_width(&self) -> isize {
+        if self.game.field.topology() == Topology::Hex { 4 } else { 3 }
+    }
+
+    /// How many columns a row is shifted to the right by.
+    fn row_shift(&self, y: isize) -> isize {
+        if self.game.field.topology() == Topology::Hex && y.rem_euclid(2) == 1 { 2 } else { 0 }
+    }
+
+    /// Draw the cell at a position on screen. Boards that wrap around are drawn over and over, so the position doesn't have to be on the board.
+    fn show_cell(&mut self, (x, y): (isize, isize)) {
+        let p = self.game.field.wrap((x, y));
+        let cell = self.game.field.get(p);
+        let (col, row) = (x*self.cell_width() + self.row_shift(y) - self.x, y-self.y);
         let (on, c) = match cell {
             Some(Cell::Hidden(flag)) => {
                 let c = match self.mode {
                     _ if flag && !(self.blink && match self.mode {
-                        DisplayMode::Risk => self.field.cell_risk(p) != 1.0,
-                        DisplayMode::Judge => self.field.definite_risk(p) != Some(true),
+                        DisplayMode::Risk => self.game.field.cell_risk(p) != 1.0,
+                        DisplayMode::Judge => self.game.field.definite_risk(p) != Some(true),
                         _ => false,
                     }) => {
                         self.iconset.flag.with(self.theme.risk_color(1.0)).bold()
                     },
                     DisplayMode::Normal => self.iconset.hidden.dim(),
                     DisplayMode::Risk => {
-                        let risk = self.field.cell_risk(p);
+                        let risk = self.game.field.cell_risk(p);
                         if risk == 1.0 {
                             self.iconset.mine.with(self.theme.risk_color(1.0))
                         } else {
@@ -109,7 +243,7 @@ impl Camera {
                             digit.with(self.theme.risk_color(risk))
                         }
                     },
-                    DisplayMode::Judge => match self.field.definite_risk(p) {
+                    DisplayMode::Judge => match self.game.field.definite_risk(p) {
                         Some(true) => self.iconset.mine.with(self.theme.risk_color(1.0)),
                         Some(false) => self.iconset.safe.with(self.theme.risk_color(0.0)),
                         None => self.iconset.unknown_risk.with(self.theme.unknown_risk),
@@ -118,82 +252,181 @@ impl Camera {
                 (self.theme.bg_hidden, c)
             },
             Some(Cell::Revealed(n)) => {
-                let c = if n == 0 { ' '.stylize() } else { char::from_digit(n as u32, 10).unwrap().with(self.theme.nums[n as usize-1]).bold() };
+                // numbers past 8 only happen in some topologies, and share the colour of 8
+                let c = if n == 0 { ' '.stylize() } else { char::from_digit(n as u32, 36).unwrap().with(self.theme.nums[n.min(8) as usize-1]).bold() };
                 (self.theme.bg_revealed, c)
             },
             None => {
-                (if self.field.is_won() { self.theme.won_void } else { self.theme.void }, ' '.stylize())
+                (if self.game.field.is_won() { self.theme.won_void } else { self.theme.void }, ' '.stylize())
             }
         };
-        self.show(col, row, ' '.on(on));
+        match self.remote.as_ref().and_then(|r| r.marker(p)) {
+            Some(marker) => self.show(col, row, marker.with(self.theme.unknown_risk).on(on).bold()),
+            None => self.show(col, row, ' '.on(on)),
+        }
         self.show(col+1, row, c.on(on));
-        self.show(col+2, row, ' '.on(on));
+        for i in 2..self.cell_width() {
+            self.show(col+i, row, ' '.on(on));
+        }
     }
 
     fn draw_entire_board(&mut self) {
         for y in self.y..self.y+self.h as isize {
-            for x in self.x.div_euclid(3)..=(self.x+self.w as isize).div_euclid(3) {
+            let width = self.cell_width();
+            for x in (self.x - self.row_shift(y)).div_euclid(width)..=(self.x+self.w as isize).div_euclid(width) {
                 self.show_cell((x, y));
             }
         }
+        self.draw_menu();
+        self.draw_log();
+        self.draw_scores();
+    }
+
+    /// Draw everyone's scores over the top-right corner of the board, if we're playing on a server.
+    fn draw_scores(&mut self) {
+        let Some(remote) = &self.remote else { return };
+        let title = remote.round.map_or(" players".to_owned(), |round| format!(" versus, round {}", round + 1));
+        let winner = remote.winner.and_then(|id| remote.players.get(&id)).map(|player| format!(" {} wins! r for another round", player.name));
+        let lines: Vec<String> = std::iter::once(title)
+            .chain(remote.players.iter().map(|(&id, player)| format!(
+                " {}{:<w$} {:>6}{}", if id == remote.id { '>' } else { ' ' }, player.name, player.score,
+                match player.cleared {
+                    Some(time) => format!(" cleared in {}", format_duration(time)),
+                    None if !player.alive => " dead".to_owned(),
+                    None => String::new(),
+                },
+                w = 16,
+            )))
+            .chain(winner.into_iter().flat_map(|line| [String::new(), line]))
+            .collect();
+        let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) + 1;
+        let (left, bg) = (self.w as isize - width as isize - 1, self.theme.bg_revealed);
+        for (row, line) in lines.into_iter().enumerate() {
+            for (col, c) in format!("{line:<width$}").chars().enumerate() {
+                self.show(left + col as isize, row as isize + 1, c.on(bg));
+            }
+        }
+    }
+
+    /// Draw the settings menu over the top-left corner of the board, if it's open.
+    fn draw_menu(&mut self) {
+        let Some(menu) = &self.menu else { return };
+        let (bg, selected) = (self.theme.bg_revealed, menu.selected);
+        for (row, line) in menu.lines().into_iter().enumerate() {
+            let line = format!("{line:<width$}", width = Menu::WIDTH);
+            for (col, c) in line.chars().enumerate() {
+                let c = if row == selected + 1 { c.on(bg).bold() } else { c.on(bg) };
+                self.show(col as isize + 1, row as isize + 1, c);
+            }
+        }
+    }
+
+    /// Draw the latest of the judge's decisions over the top-left corner of the board, if they're being shown.
+    fn draw_log(&mut self) {
+        if !self.log_open {
+            return;
+        }
+        let decisions = self.game.field.decisions();
+        let skipped = decisions.len().saturating_sub((self.h as usize).saturating_sub(4));
+        let lines: Vec<String> = std::iter::once(" judge's decisions, latest last".to_owned())
+            .chain(decisions.skip(skipped).map(|d| format!(" {d}")))
+            .chain([String::new(), " l to close".to_owned()])
+            .collect();
+        let width = lines.iter().map(|l| l.len()).max().unwrap_or(0) + 1;
+        let bg = self.theme.bg_revealed;
+        for (row, line) in lines.into_iter().enumerate() {
+            for (col, c) in format!("{line:<width$}").chars().enumerate() {
+                self.show(col as isize + 1, row as isize + 1, c.on(bg));
+            }
+        }
+    }
+
+    fn open_menu(&mut self) {
+        self.menu = Some(Menu { judge: self.game.field.judge(), solvable: self.game.field.solvable(), selected: 0 });
+        self.draw_menu();
+    }
+
+    fn close_menu(&mut self, apply: bool) {
+        if let Some(menu) = self.menu.take() && apply {
+            // the change is recorded with the time it happened at
+            self.game.pass_time();
+            self.game.field.set_rules(menu.judge, menu.solvable, self.game.field.tolerance());
+        }
+        self.draw_entire_board();
+    }
+
+    fn menu_key(&mut self, code: KeyCode) {
+        let Some(menu) = &mut self.menu else { return };
+        match code {
+            KeyCode::Esc | KeyCode::Char('o') => return self.close_menu(false),
+            KeyCode::Enter => return self.close_menu(true),
+            KeyCode::Up | KeyCode::Char('w') => menu.selected = (menu.selected + Menu::SETTINGS - 1) % Menu::SETTINGS,
+            KeyCode::Down | KeyCode::Char('s') => menu.selected = (menu.selected + 1) % Menu::SETTINGS,
+            KeyCode::Left | KeyCode::Char('a') => menu.change(-1),
+            KeyCode::Right | KeyCode::Char('d') => menu.change(1),
+            _ => (),
+        }
+        self.draw_menu();
     }
 
     fn clicked_cell(&self, col: u16, row: u16) -> (isize, isize) {
-        ((self.x+col as isize).div_euclid(3), self.y+row as isize)
+        let y = self.y+row as isize;
+        self.game.field.wrap(((self.x+col as isize - self.row_shift(y)).div_euclid(self.cell_width()), y))
+    }
+
+    /// Draw a cell that has changed. A board that wraps around can be on screen more than once, so that means drawing everything.
+    fn show_changed(&mut self, p: (isize, isize)) {
+        if self.game.field.wraps() {
+            self.draw_entire_board();
+        } else {
+            self.show_cell(p);
+        }
     }
 
     fn click(&mut self, col: u16, row: u16) {
-        if self.dead {
+        self.reveal(self.clicked_cell(col, row));
+    }
+
+    fn reveal(&mut self, clicked: (isize, isize)) {
+        if self.watched.is_some() {
             return;
         }
-        let mut queue = VecDeque::new();
-        let clicked = self.clicked_cell(col, row);
-        match self.field.get(clicked) {
-            Some(Cell::Revealed(n)) if n as usize == adjacents(clicked).filter(|&x| self.field.get(x) == Some(Cell::Hidden(true))).count() => {
-                queue.extend(adjacents(clicked).filter(|&x| self.field.get(x) == Some(Cell::Hidden(false))));
+        if let Some(remote) = &mut self.remote {
+            if !self.game.dead {
+                remote.connection.send(ToServer::Reveal(clicked));
             }
-            _ => queue.push_back(clicked),
-        }
-        let mut done = 0;
-        while !queue.is_empty() && done < 2401 {
-            let pos = queue.pop_front().unwrap();
-            let Some(Cell::Hidden(false)) = self.field.get(pos) else { continue };
-            match self.field.reveal_cell_first_zero(pos) {
-                Some(n) => {
-                    if n == 0 {
-                        queue.extend(adjacents(pos));
-                    }
-                    if self.mode == DisplayMode::Normal {
-                        self.show_cell(pos);
-                    }
-                },
-                None => {
-                    self.dead = true;
-                    self.mode = DisplayMode::Judge;
-                    self.draw_entire_board();
-                    self.pass_time();
-                    return;
-                },
-            }
-            done += 1;
+            return;
         }
-        self.init_time();
-        if done >= 1 && self.field.is_won() {
-            self.pass_time();
-        } else if self.mode == DisplayMode::Normal {
+        let reveal = self.game.reveal(clicked);
+        // winning changes the colour of the whole board
+        let won = !reveal.revealed.is_empty() && self.game.field.is_won();
+        if reveal.death.is_some() {
+            self.mode = DisplayMode::Judge;
+        } else if !won && self.mode == DisplayMode::Normal && !self.game.field.wraps() {
+            for (p, _) in reveal.revealed {
+                self.show_cell(p);
+            }
             return;
         }
         self.draw_entire_board();
     }
 
     fn flag(&mut self, col: u16, row: u16) {
-        if self.dead {
+        let pos = self.clicked_cell(col, row);
+        if self.watched.is_some() {
+            return;
+        }
+        if let Some(remote) = &mut self.remote {
+            if !self.game.dead {
+                remote.connection.send(ToServer::Flag(pos));
+            }
+            return;
+        }
+        if !self.game.flag(pos) {
             return;
         }
-        let pos = self.clicked_cell(col, row);
-        self.field.toggle_flag(pos);
         let blink = std::mem::replace(&mut self.blink, false);
-        self.show_cell(pos);
+        self.show_changed(pos);
         self.blink = blink;
     }
 
@@ -203,34 +436,162 @@ impl Camera {
         self.draw_entire_board();
     }
 
-    fn init_time(&mut self) {
-        if !self.field.risks().is_empty() && self.last_time_pass.is_none() {
-            self.last_time_pass = Some(Instant::now());
+    fn centre_on(&mut self, (x, y): (isize, isize)) {
+        self.x = x*self.cell_width() + self.row_shift(y) + 1 - self.w as isize / 2;
+        self.y = y - self.h as isize / 2;
+        self.draw_entire_board();
+    }
+
+    fn find_nearest(&mut self) {
+        let centre = self.clicked_cell(self.w / 2, self.h / 2);
+        // when risks are visible, look for the safest tiles. otherwise any tile on the frontier will do
+        let max_risk = if self.mode == DisplayMode::Normal { 1.0 } else { self.game.field.risks().global_best() };
+        if let Some((point, _)) = self.game.field.risks().nearest(centre, max_risk) {
+            self.centre_on(point);
         }
     }
 
-    fn pass_time(&mut self) {
-        if let Some(ltp) = self.last_time_pass {
-            self.field.pass_time(ltp.elapsed());
-            self.last_time_pass = Some(Instant::now());
+    fn on_screen(&self, (x, y): (isize, isize)) -> bool {
+        let (col, row) = (x*self.cell_width() + self.row_shift(y) - self.x, y-self.y);
+        (0..self.w as isize).contains(&col) && (0..self.h as isize).contains(&row)
+    }
+
+    fn toggle_autoplay(&mut self) {
+        self.autoplay = match self.autoplay {
+            Some(_) => None,
+            None => Some(self.bot.strategy()),
+        };
+    }
+
+    /// Let the bot make a move, following it around the board. Autoplay stops when the game ends or the bot has nothing to do.
+    fn autoplay_step(&mut self) {
+        let Some(strategy) = &mut self.autoplay else { return };
+        // every game starts with a click in the middle of the screen
+        let action = if self.game.field.cells_revealed() == 0 {
+            Some(Action::Reveal(self.clicked_cell(self.w / 2, self.h / 2)))
+        } else {
+            strategy.next_action(&self.game.field)
+        };
+        match action {
+            Some(Action::Reveal(point)) => {
+                if !self.on_screen(point) {
+                    self.centre_on(point);
+                }
+                self.reveal(point);
+            },
+            Some(Action::Flag(point)) => if self.game.flag(point) {
+                self.show_changed(point);
+            },
+            None => self.autoplay = None,
+        }
+        if self.game.is_over() {
+            self.autoplay = None;
         }
     }
 
-    fn save(&mut self) {
-        if !self.dead && !self.field.is_won() {
-            self.pass_time();
+    /// Tell the server which tile the mouse is over, if it's changed.
+    fn move_cursor(&mut self, col: u16, row: u16) {
+        let point = self.clicked_cell(col, row);
+        if let Some(remote) = &mut self.remote && remote.cursor != Some(point) {
+            remote.cursor = Some(point);
+            remote.connection.send(ToServer::Cursor(point));
         }
-        self.save_file.rewind().expect("failed to rewind");
-        self.save_file.set_len(0).expect("failed to truncate");
-        self.field.save(&mut self.save_file).expect("failed to write to save file");
-        self.save_file.flush().expect("failed to flush");
     }
 
-    fn load(&mut self) {
-        self.save_file.rewind().expect("failed to rewind");
-        let mut r = Field::load(&mut self.save_file).expect("failed to read save file");
-        std::mem::swap(&mut self.field, &mut r);
-        self.init_time();
+    /// Show the game being watched again if it's been saved.
+    fn reload(&mut self) {
+        if let Some(watched) = &mut self.watched && let Some(field) = watched.changed() {
+            self.game.field = field;
+            self.draw_entire_board();
+        }
+    }
+
+    /// Catch up on everything the server has said. Returns false if it's gone.
+    fn receive(&mut self) -> bool {
+        let Some(remote) = &mut self.remote else { return true };
+        let mut redraw = false;
+        let mut changed = Vec::new();
+        loop {
+            let message = match remote.connection.messages.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return false,
+            };
+            match message {
+                ToClient::Welcome(_) | ToClient::Full => {},
+                ToClient::Board(bytes) => {
+                    let Ok(field) = Field::load(&mut &bytes[..]) else { return false };
+                    self.game.field = field;
+                    remote.resyncing = false;
+                    redraw = true;
+                },
+                // anything that changed before the board was sent again is already on it
+                ToClient::Revealed(..) | ToClient::Mine(_) | ToClient::Flagged(..) if remote.resyncing => {},
+                // the server already decided the number, so all that's left is to work out the risks it changes
+                ToClient::Revealed(point, num) => if self.game.field.reveal_known(point, num) {
+                    // winning changes the colour of the whole board, and any risks that are shown can change anywhere near the tile
+                    redraw |= self.mode != DisplayMode::Normal || self.game.field.is_won();
+                    changed.push(point);
+                } else {
+                    remote.resync();
+                },
+                ToClient::Mine(point) => if self.game.field.mark_mine(point) {
+                    redraw |= self.mode != DisplayMode::Normal;
+                } else {
+                    remote.resync();
+                },
+                ToClient::Flagged(point, flagged) => match self.game.field.get(point) {
+                    Some(Cell::Hidden(was)) => if was != flagged {
+                        self.game.field.toggle_flag(point);
+                        changed.push(point);
+                    },
+                    _ => remote.resync(),
+                },
+                ToClient::Player { id, score, alive, name } => {
+                    let player = remote.players.entry(id).or_insert_with(|| Player::new(String::new()));
+                    player.name = name;
+                    player.score = score;
+                    player.alive = alive;
+                    if id == remote.id && self.game.dead == alive {
+                        self.game.dead = !alive;
+                        self.mode = if alive { Self::default_mode(self.cheat) } else { DisplayMode::Judge };
+                    }
+                    redraw = true;
+                },
+                ToClient::Left(id) => {
+                    remote.players.remove(&id);
+                    redraw = true;
+                },
+                ToClient::Cursor(id, point) => if let Some(player) = remote.players.get_mut(&id) {
+                    changed.extend(player.cursor.replace(point));
+                    changed.push(point);
+                },
+                ToClient::Round(round) => {
+                    remote.round = Some(round);
+                    remote.winner = None;
+                    // everyone starts the round in the same place
+                    self.x = -(self.w as isize) / 2;
+                    self.y = -(self.h as isize) / 2;
+                    redraw = true;
+                },
+                ToClient::Cleared(id, time) => if let Some(player) = remote.players.get_mut(&id) {
+                    player.cleared = Some(time);
+                    redraw = true;
+                },
+                ToClient::Winner(id) => {
+                    remote.winner = Some(id);
+                    redraw = true;
+                },
+            }
+        }
+        if redraw {
+            self.draw_entire_board();
+        } else {
+            for p in changed {
+                self.show_changed(p);
+            }
+        }
+        true
     }
 }
 
@@ -250,7 +611,34 @@ fn fix_terminal() -> Result<()> {
     Ok(())
 }
 
-pub fn game_loop(args: Args, save_path: std::path::PathBuf) -> Result<()> {
+pub fn game_loop(args: Args, save_path: PathBuf) -> Result<()> {
+    let game = Game::open(&args, SaveSlot::new(save_path), true);
+    play(args, game, None, None)
+}
+
+/// Play on the board of the server at an address.
+pub fn connect(args: Args, address: &str, name: &str) -> Result<()> {
+    let (connection, id, field) = Connection::open(address, name)?;
+    let remote = Remote { connection, id, players: BTreeMap::new(), cursor: None, round: None, winner: None, resyncing: false };
+    play(args, Game::remote(field), Some(remote), None)
+}
+
+/// Show the game in the save file as it's played somewhere else.
+pub fn watch(args: Args, save_path: PathBuf) -> Result<()> {
+    let mut watched = Watched { slot: SaveSlot::new(save_path), modified: None };
+    let Some(field) = watched.changed() else {
+        eprintln!("there's no game to watch. the save file has to exist and load");
+        std::process::exit(1);
+    };
+    play(args, Game::remote(field), None, Some(watched))
+}
+
+fn play(args: Args, game: Game, remote: Option<Remote>, watched: Option<Watched>) -> Result<()> {
+    let autosave = args.autosave;
+    let audit_log = args.audit_log.clone();
+    let mut cam = Camera::new(&args, game, remote, terminal::size()?);
+    cam.watched = watched;
+
     terminal::enable_raw_mode()?;
     queue!(stdout(), terminal::EnterAlternateScreen, terminal::DisableLineWrap, cursor::Hide, EnableMouseCapture)?;
 
@@ -260,31 +648,43 @@ pub fn game_loop(args: Args, save_path: std::path::PathBuf) -> Result<()> {
         prev_hook(info);
     }));
 
-    let exists = save_path.exists();
-    let file = std::fs::File::options().read(true).write(true).create(true).truncate(false).open(save_path);
-    let autosave = args.autosave;
-    let reset = args.reset;
-    let mut cam = Camera::new(args, file.expect("failed to open save file"), terminal::size()?);
-    if exists && !reset {
-        cam.load();
-    } else {
-        cam.save();
-    }
-
     let mut speed = 1;
     let mut hold = None;
     let mut click_active = false;
     let mut blink_start = Instant::now();
+    let mut next_step = Instant::now();
+    let mut disconnected = false;
     cam.draw_entire_board();
 
     loop {
+        if !cam.receive() {
+            disconnected = true;
+            break;
+        }
+        cam.reload();
         stdout().flush()?;
 
-        if cam.mode != DisplayMode::Normal {
-            // blinking
-            if !poll(Duration::from_secs_f64(1.0 - blink_start.elapsed().as_secs_f64() % 1.0))? {
-                cam.blink = !cam.blink;
-                cam.draw_entire_board();
+        let autoplaying = cam.autoplay.is_some() && cam.menu.is_none();
+        let blinking = cam.mode != DisplayMode::Normal;
+        if blinking || autoplaying || cam.elsewhere() {
+            let until_blink = Duration::from_secs_f64(1.0 - blink_start.elapsed().as_secs_f64() % 1.0);
+            let until_step = next_step.saturating_duration_since(Instant::now());
+            let mut timeout = if autoplaying { until_blink.min(until_step) } else { until_blink };
+            if cam.elsewhere() {
+                timeout = timeout.min(REMOTE_POLL);
+            }
+            if !poll(timeout)? {
+                if autoplaying && until_step <= timeout {
+                    cam.autoplay_step();
+                    next_step = Instant::now() + AUTOPLAY_DELAY;
+                    if autosave && !cam.game.dead {
+                        cam.game.save();
+                    }
+                } else if blinking && until_blink <= timeout {
+                    // blinking
+                    cam.blink = !cam.blink;
+                    cam.draw_entire_board();
+                }
                 continue;
             }
         }
@@ -300,14 +700,19 @@ pub fn game_loop(args: Args, save_path: std::path::PathBuf) -> Result<()> {
         }
 
         match ev {
+            Event::Key(event) if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) => break,
+            Event::Key(event) if cam.menu.is_some() => cam.menu_key(event.code),
+            Event::Mouse(_) if cam.menu.is_some() => {},
             Event::Key(event) => match event.code {
                 KeyCode::Esc => break,
-                KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => break,
-                KeyCode::Char('s') if event.modifiers.contains(KeyModifiers::CONTROL) => cam.save(),
+                KeyCode::Char('s') if event.modifiers.contains(KeyModifiers::CONTROL) => cam.game.save(),
                 KeyCode::Char('w') => cam.pan(0, -speed as isize),
                 KeyCode::Char('a') => cam.pan(-speed as isize, 0),
                 KeyCode::Char('s') => cam.pan(0, speed as isize),
                 KeyCode::Char('d') => cam.pan(speed as isize, 0),
+                KeyCode::Char('f') => cam.find_nearest(),
+                // the rules of a game on a server are up to the server
+                KeyCode::Char('o') if !cam.game.dead && !cam.elsewhere() => cam.open_menu(),
                 KeyCode::Char('j') => {
                     cam.mode = match cam.mode {
                         DisplayMode::Judge => DisplayMode::Risk,
@@ -318,7 +723,16 @@ pub fn game_loop(args: Args, save_path: std::path::PathBuf) -> Result<()> {
                     cam.blink = false;
                     cam.draw_entire_board();
                 },
-                KeyCode::Char('r') => if cam.dead { cam.reset() },
+                // on a server, it's up to the server whether there's anything to start again
+                KeyCode::Char('r') if (cam.game.dead && cam.watched.is_none()) || cam.remote.is_some() => cam.reset(),
+                KeyCode::Char('p') if !cam.game.dead && !cam.elsewhere() => {
+                    cam.toggle_autoplay();
+                    next_step = Instant::now();
+                },
+                KeyCode::Char('l') if cam.game.dead => {
+                    cam.log_open = !cam.log_open;
+                    cam.draw_entire_board();
+                },
                 _ => {},
             },
             Event::Resize(w, h) => {
@@ -340,32 +754,59 @@ pub fn game_loop(args: Args, save_path: std::path::PathBuf) -> Result<()> {
                     hold = None;
                     if click_active {
                         cam.click(event.column, event.row);
-                        if autosave && !cam.dead {
-                            cam.save();
+                        if autosave && !cam.game.dead {
+                            cam.game.save();
                         }
                         click_active = false;
                     }
                 },
                 MouseEventKind::Down(MouseButton::Right) => cam.flag(event.column, event.row),
-                MouseEventKind::ScrollDown => if speed > 1 { speed -= 1 },
-                MouseEventKind::ScrollUp => if speed < 10 { speed += 1 },
+                MouseEventKind::Moved => cam.move_cursor(event.column, event.row),
+                MouseEventKind::ScrollDown if speed > 1 => speed -= 1,
+                MouseEventKind::ScrollUp if speed < 10 => speed += 1,
                 _ => {},
             },
             _ => {},
         }
     }
 
-    cam.save();
+    cam.game.save();
     fix_terminal()?;
+    if let Some(path) = audit_log {
+        cam.game.write_audit_log(&path);
+    }
 
-    let status = if cam.dead {
+    if cam.watched.is_some() {
+        eprintln!("Stopped watching after {} tiles were revealed", cam.game.field.cells_revealed());
+        return Ok(());
+    }
+    if let Some(remote) = &cam.remote {
+        if disconnected {
+            eprintln!("Lost the connection to the server");
+        }
+        if let Some(me) = remote.me() {
+            eprintln!("You revealed {} tiles{}", me.score, if me.alive { "" } else { " before dying" });
+        }
+        if let Some(winner) = remote.winner.and_then(|id| remote.players.get(&id)) {
+            eprintln!("{} won the last round", winner.name);
+        }
+        return Ok(());
+    }
+
+    let status = if cam.game.dead {
         "Better luck next time"
-    } else if cam.field.is_won() {
+    } else if cam.game.field.is_won() {
         "Well done"
     } else {
         "See you later"
     };
-    eprintln!("{status}\n{} tiles revealed\nTotal playtime {}", cam.field.cells_revealed(), format_duration(cam.field.time_elapsed()));
+    eprintln!("{status}\n{} tiles revealed\nTotal playtime {}", cam.game.field.cells_revealed(), format_duration(cam.game.field.time_elapsed()));
+    if cam.game.dead && let Some(fatal) = cam.game.field.decisions().next_back() {
+        eprintln!("The judge's last decision was {fatal}");
+    }
+    if cam.game.field.mixed_rules() {
+        eprintln!("The rules were changed {} times during this game", cam.game.field.rule_history().len());
+    }
 
     Ok(())
 }